@@ -4,20 +4,22 @@ use std::time;
 
 fn main() {
     let mut conn = DiscordRPC::new("683696447815811121");
-    conn.start();
+    conn.start().unwrap();
 
     loop {
         let mut line = String::new();
-        io::stdin().read_line(&mut line);
-        if line.chars().next() == Some('c') {
-            conn.set_rich_presence(None);
+        io::stdin().read_line(&mut line).unwrap();
+        if line.starts_with('c') {
+            conn.set_rich_presence(None).unwrap();
         }
         else {
-            let mut rp = RichPresence::default();
-            rp.state = "Doing stuff".into();
-            rp.details = "More stuff...".into();
-            rp.start_timestamp = Some(time::SystemTime::now());
-            conn.set_rich_presence(Some(rp));
+            let rp = RichPresence{
+                state: "Doing stuff".into(),
+                details: "More stuff...".into(),
+                start_timestamp: Some(time::SystemTime::now()),
+                ..Default::default()
+            };
+            conn.set_rich_presence(Some(rp)).unwrap();
         }
     }
 }
\ No newline at end of file
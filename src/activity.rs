@@ -0,0 +1,97 @@
+//! Inviting users to join or spectate the activity.
+
+use serde_json as json;
+use crate::{DiscordRpcHandle, Message, Response, User, Error, pid};
+
+/// What an activity invite is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActivityActionType {
+    /// Joining the activity.
+    Join,
+    /// Spectating the activity.
+    Spectate,
+}
+
+impl ActivityActionType {
+    /// Returns the number of the action type in the RPC protocol.
+    pub(crate) fn as_i32(self) -> i32 {
+        match self {
+            Self::Join => 1,
+            Self::Spectate => 2,
+        }
+    }
+
+    /// Creates an `ActivityActionType` from its number in the RPC protocol.
+    fn from_i32(n: i32) -> Option<Self> {
+        match n {
+            1 => Some(Self::Join),
+            2 => Some(Self::Spectate),
+            _ => None,
+        }
+    }
+}
+
+/// An invite to join or spectate the activity of another user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivityInvite {
+    /// The user who sent the invite.
+    pub user: User,
+    /// What the invite is for.
+    pub ty: ActivityActionType,
+    /// The ID of the channel the invite was sent in.
+    pub channel_id: String,
+    /// The ID of the message of the invite.
+    pub message_id: String,
+    /// The activity the user was invited to, as Discord sent it.
+    pub activity: json::Value,
+}
+
+impl ActivityInvite {
+    /// Creates an `ActivityInvite` from its JSON representation. Returns
+    /// `None`, if it's incomplete.
+    pub(crate) fn from_json(json: &json::Value) -> Option<Self> {
+        Some(Self{
+            user: User::from_json(&json["user"])?,
+            ty: ActivityActionType::from_i32(json["type"].as_i64()? as i32)?,
+            channel_id: json["channel_id"].as_str()?.to_string(),
+            message_id: json["message_id"].as_str()?.to_string(),
+            activity: json["activity"].clone(),
+        })
+    }
+}
+
+impl DiscordRpcHandle {
+    /// Invites the user with the given ID to join or spectate the current
+    /// activity, with an optional message. The activity has to be set with
+    /// the matching secrets before.
+    pub fn send_activity_invite(
+        &self,
+        user_id: &str,
+        ty: ActivityActionType,
+        content: Option<&str>,
+    ) -> Result<Response, Error> {
+        let mut args = json::json!{{
+            "pid": pid(),
+            "user_id": user_id,
+            "type": ty.as_i32(),
+        }};
+        if let Some(content) = content {
+            args["content"] = json::Value::String(content.to_string());
+        }
+        self.shared.send(Message::command("SEND_ACTIVITY_INVITE", args))
+    }
+
+    /// Accepts the given `ActivityInvite`. The join or spectate event with the
+    /// secret arrives, once Discord launched the game.
+    pub fn accept_activity_invite(&self, invite: &ActivityInvite) -> Result<Response, Error> {
+        let args = json::json!{{
+            "pid": pid(),
+            "type": invite.ty.as_i32(),
+            "user_id": invite.user.id,
+            "session_id": invite.activity["session_id"],
+            "channel_id": invite.channel_id,
+            "message_id": invite.message_id,
+        }};
+        self.shared.send(Message::command("ACCEPT_ACTIVITY_INVITE", args))
+    }
+}
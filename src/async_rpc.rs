@@ -0,0 +1,296 @@
+//! The asynchronous Discord RPC client, built on tokio.
+
+#![cfg(feature = "tokio")]
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::collections::HashMap;
+use futures_core::Stream;
+use serde_json as json;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use crate::{AsyncConnection, Message, MessageType, RichPresence, ReadyInfo, Event, Error, RpcError};
+
+/// The commands waiting for their response, keyed by nonce. `None` once the
+/// reader stopped, so no command can wait for an answer that never comes.
+type PendingMap = Arc<Mutex<Option<HashMap<String, oneshot::Sender<Result<json::Value, Error>>>>>>;
+
+/// The asynchronous Discord RPC client to communicate with the local Discord
+/// server. The connection is not re-established once lost, a new client has
+/// to be connected then.
+#[derive(Debug)]
+pub struct AsyncDiscordRpc {
+    writer: mpsc::UnboundedSender<Message>,
+    pending: PendingMap,
+    events: Option<EventStream>,
+    ready_info: ReadyInfo,
+    reader_handle: JoinHandle<()>,
+}
+
+impl AsyncDiscordRpc {
+    /// Connects to the Discord server with the given application ID and the
+    /// default connection type. Resolves when the server is ready to receive
+    /// commands.
+    #[cfg(unix)]
+    pub async fn connect(app_id: &str) -> Result<Self, Error> {
+        Self::connect_with(crate::AsyncIpcConnection::new(), app_id).await
+    }
+
+    /// Connects to the Discord server with the given `AsyncConnection` and
+    /// application ID. Resolves when the server is ready to receive commands.
+    pub async fn connect_with<C: AsyncConnection>(mut conn: C, app_id: &str) -> Result<Self, Error> {
+        let mut stream = conn.open().await
+            .map_err(|err| Error::PipeClosed(err.to_string()))?;
+
+        // Handshake, then wait for the server to become ready
+        write_message(&mut stream, &Message::handshake(app_id)).await?;
+        let ready_info = loop {
+            let mut message = read_message(&mut stream).await?;
+            match message.ty() {
+                MessageType::Frame => {
+                    if message.value("cmd") == Some("DISPATCH") && message.value("evt") == Some("READY") {
+                        break ReadyInfo::from_json(&message.payload()["data"]);
+                    }
+                },
+                MessageType::Close => {
+                    return Err(Error::connection_closed(&message));
+                },
+                MessageType::Ping => {
+                    message.set_ty(MessageType::Pong);
+                    write_message(&mut stream, &message).await?;
+                },
+                MessageType::Pong => {
+                    // No-op
+                },
+                x => {
+                    return Err(Error::InvalidMessage(format!(
+                        "Message of type {:?} can't be sent by the server!", x)));
+                },
+            }
+        };
+
+        let (reader, writer) = tokio::io::split(stream);
+        let (writer_sender, writer_receiver) = mpsc::unbounded_channel();
+        let (event_sender, event_receiver) = mpsc::unbounded_channel();
+        let pending: PendingMap = Arc::new(Mutex::new(Some(HashMap::new())));
+
+        tokio::spawn(write_loop(writer, writer_receiver));
+        let reader_handle = tokio::spawn(read_loop(
+            reader, writer_sender.clone(), pending.clone(), event_sender));
+
+        Ok(Self{
+            writer: writer_sender,
+            pending,
+            events: Some(EventStream{ receiver: event_receiver }),
+            ready_info,
+            reader_handle,
+        })
+    }
+
+    /// Returns the `ReadyInfo` the server sent when the connection was made.
+    pub fn ready_info(&self) -> &ReadyInfo {
+        &self.ready_info
+    }
+
+    /// Sets the `RichPresence` for the Discord server. Resolves with the
+    /// response of the server.
+    pub async fn set_activity(&self, rp: Option<RichPresence>) -> Result<json::Value, Error> {
+        self.send(Message::rich_presence(rp)).await
+    }
+
+    /// Sends an arbitrary command with the given arguments to the Discord
+    /// server. Resolves with the `data` of the answer.
+    pub async fn send_raw(&self, cmd: &str, args: json::Value) -> Result<json::Value, Error> {
+        self.send(Message::command(cmd, args)).await
+    }
+
+    /// Returns the `Stream` of events dispatched by the server. The stream ends
+    /// when the connection is lost. Can only be taken once, returns `None`
+    /// afterwards.
+    pub fn events(&mut self) -> Option<EventStream> {
+        self.events.take()
+    }
+
+    /// Sends a `Message` to the Discord server and resolves with the response
+    /// correlated by the nonce of the `Message`.
+    async fn send(&self, message: Message) -> Result<json::Value, Error> {
+        let nonce = message.value("nonce").unwrap_or_default().to_string();
+        let (sender, receiver) = oneshot::channel();
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(nonce.clone(), sender),
+            None => return Err(connection_lost()),
+        };
+        if self.writer.send(message).is_err() {
+            if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+                pending.remove(&nonce);
+            }
+            return Err(connection_lost());
+        }
+        receiver.await.unwrap_or_else(|_| Err(connection_lost()))
+    }
+}
+
+impl Drop for AsyncDiscordRpc {
+    fn drop(&mut self) {
+        // The writer stops by itself, when its channel closes
+        self.reader_handle.abort();
+    }
+}
+
+/// The `Stream` of `Event`s dispatched by the Discord server.
+#[derive(Debug)]
+pub struct EventStream {
+    receiver: mpsc::UnboundedReceiver<Event>,
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// The error for when the connection is gone before a response arrived.
+fn connection_lost() -> Error {
+    Error::PipeClosed("Connection lost before a response arrived".into())
+}
+
+/// Reads a single `Message` from the given stream.
+async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Message, Error> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header).await
+        .map_err(|err| Error::PipeClosed(err.to_string()))?;
+    let (ty, len) = Message::decode_header(header)?;
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await
+        .map_err(|err| Error::PipeClosed(err.to_string()))?;
+    Message::decode_payload(ty, payload)
+}
+
+/// Writes a single `Message` to the given stream.
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> Result<(), Error> {
+    writer.write_all(&message.encode()).await
+        .map_err(|err| Error::PipeClosed(err.to_string()))
+}
+
+/// Writes the queued `Message`s, until the queue or the stream closes.
+async fn write_loop<W: AsyncWrite + Unpin>(mut writer: W, mut messages: mpsc::UnboundedReceiver<Message>) {
+    while let Some(message) = messages.recv().await {
+        if write_message(&mut writer, &message).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Reads `Message`s and dispatches them to the waiting commands or the event
+/// stream, until the connection is lost.
+async fn read_loop<R: AsyncRead + Unpin>(
+    mut reader: R,
+    writer: mpsc::UnboundedSender<Message>,
+    pending: PendingMap,
+    events: mpsc::UnboundedSender<Event>,
+) {
+    let err = loop {
+        let mut message = match read_message(&mut reader).await {
+            Ok(message) => message,
+            Err(err) => break err,
+        };
+        match message.ty() {
+            MessageType::Frame => {
+                if let Some(nonce) = message.value("nonce") {
+                    let result = if message.value("evt") == Some("ERROR") {
+                        let err = Error::Rpc(RpcError::from_message(&message));
+                        let _ = events.send(Event::Error(err.clone()));
+                        Err(err)
+                    }
+                    else {
+                        Ok(message.payload()["data"].clone())
+                    };
+                    let sender = pending.lock().unwrap().as_mut().and_then(|p| p.remove(nonce));
+                    if let Some(sender) = sender {
+                        let _ = sender.send(result);
+                    }
+                }
+                else if let Some(event) = Event::from_message(&message) {
+                    let _ = events.send(event);
+                }
+            },
+            MessageType::Close => {
+                break Error::connection_closed(&message);
+            },
+            MessageType::Ping => {
+                message.set_ty(MessageType::Pong);
+                let _ = writer.send(message);
+            },
+            MessageType::Pong => {
+                // No-op
+            },
+            x => {
+                break Error::InvalidMessage(format!(
+                    "Message of type {:?} can't be sent by the server!", x));
+            },
+        }
+    };
+
+    // Nothing will be answered anymore, later commands fail right away
+    let senders = pending.lock().unwrap().take().unwrap_or_default();
+    for (_, sender) in senders {
+        let _ = sender.send(Err(err.clone()));
+    }
+    let _ = events.send(Event::Error(err));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::DuplexStream;
+
+    /// An `AsyncConnection` to an in-memory server.
+    struct MemoryConnection(Option<DuplexStream>);
+
+    impl AsyncConnection for MemoryConnection {
+        type Stream = DuplexStream;
+
+        async fn open(&mut self) -> std::io::Result<DuplexStream> {
+            self.0.take().ok_or_else(|| std::io::ErrorKind::NotConnected.into())
+        }
+    }
+
+    /// Starts a server that answers the handshake, then closes the connection.
+    fn closing_server() -> MemoryConnection {
+        let (client, mut server) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let handshake = read_message(&mut server).await.unwrap();
+            assert_eq!(handshake.ty(), MessageType::Handshake);
+            let ready = Message::new(MessageType::Frame, json::json!{{
+                "cmd": "DISPATCH",
+                "evt": "READY",
+                "data": { "v": 1 },
+            }});
+            write_message(&mut server, &ready).await.unwrap();
+        });
+        MemoryConnection(Some(client))
+    }
+
+    #[tokio::test]
+    async fn send_after_close_fails() {
+        let mut rpc = AsyncDiscordRpc::connect_with(closing_server(), "1").await.unwrap();
+        // The reader reports the lost connection as its last event
+        let mut events = rpc.events().unwrap();
+        loop {
+            match std::future::poll_fn(|cx| Pin::new(&mut events).poll_next(cx)).await {
+                Some(Event::Error(Error::PipeClosed(_))) | None => break,
+                Some(_) => {},
+            }
+        }
+
+        for _ in 0..2 {
+            let result = tokio::time::timeout(Duration::from_secs(2), rpc.send_raw("TEST", json::json!{{}})).await;
+            assert!(matches!(result, Ok(Err(Error::PipeClosed(_)))));
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! The OAuth2 authorization flow over RPC.
+//!
+//! Privileged commands need an authenticated connection. Getting there takes
+//! three steps: `AUTHORIZE` asks the user for consent and yields a code, the
+//! code is exchanged for a token over HTTP by a `TokenExchange`, and finally
+//! `AUTHENTICATE` logs the connection in with the token.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use serde_json as json;
+use crate::{DiscordRpcHandle, Message, TokenStore, User, Error, RpcErrorCode};
+
+/// An OAuth2 token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /// The token used to authenticate.
+    pub access_token: String,
+    /// The token used to get a new access token, when this one expires.
+    pub refresh_token: Option<String>,
+    /// The time the access token expires at, if known.
+    pub expires_at: Option<SystemTime>,
+    /// The scopes the token was granted for.
+    pub scopes: Vec<String>,
+}
+
+impl Token {
+    /// Returns `true`, if the access token is known to be expired.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|t| t <= SystemTime::now())
+    }
+
+    /// Creates a `Token` from an OAuth2 token endpoint response.
+    pub fn from_json(json: &json::Value) -> Result<Self, Error> {
+        let access_token = json["access_token"].as_str()
+            .ok_or_else(|| Error::Auth("No access token in the token response".into()))?;
+        Ok(Self{
+            access_token: access_token.to_string(),
+            refresh_token: json["refresh_token"].as_str().map(str::to_string),
+            expires_at: json["expires_in"].as_u64()
+                .map(|secs| SystemTime::now() + Duration::from_secs(secs)),
+            scopes: json["scope"].as_str().unwrap_or_default()
+                .split_whitespace().map(str::to_string).collect(),
+        })
+    }
+}
+
+/// Exchanges OAuth2 codes for tokens, usually by talking to an HTTP endpoint.
+pub trait TokenExchange: Send + Sync {
+    /// Exchanges the code received from `AUTHORIZE` for a `Token`.
+    fn exchange_code(&self, code: &str) -> Result<Token, Error>;
+
+    /// Gets a new `Token` using a refresh token.
+    fn refresh(&self, refresh_token: &str) -> Result<Token, Error>;
+}
+
+/// A `TokenExchange` posting to an OAuth2 token endpoint over HTTP. By default
+/// it talks to Discord directly, which needs the client secret. The base URL
+/// can point to a backend of our own instead, that keeps the secret. Needs
+/// the `http` feature.
+#[cfg(feature = "http")]
+#[derive(Debug, Clone)]
+pub struct HttpTokenExchange {
+    base_url: String,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_uri: Option<String>,
+}
+
+#[cfg(feature = "http")]
+impl HttpTokenExchange {
+    /// The base URL of the Discord API.
+    pub const DISCORD_API: &'static str = "https://discord.com/api";
+
+    /// Creates a new `HttpTokenExchange` for the given application ID,
+    /// talking to the Discord API.
+    pub fn new(client_id: &str) -> Self {
+        Self{
+            base_url: Self::DISCORD_API.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: None,
+            redirect_uri: None,
+        }
+    }
+
+    /// Sets the base URL the `/oauth2/token` endpoint is under.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sets the client secret to send along.
+    pub fn with_client_secret(mut self, client_secret: &str) -> Self {
+        self.client_secret = Some(client_secret.to_string());
+        self
+    }
+
+    /// Sets the redirect URI to send along.
+    pub fn with_redirect_uri(mut self, redirect_uri: &str) -> Self {
+        self.redirect_uri = Some(redirect_uri.to_string());
+        self
+    }
+
+    /// Posts the given grant to the token endpoint.
+    fn request(&self, grant: &[(&str, &str)]) -> Result<Token, Error> {
+        let mut form = grant.to_vec();
+        form.push(("client_id", &self.client_id));
+        if let Some(client_secret) = &self.client_secret {
+            form.push(("client_secret", client_secret));
+        }
+        if let Some(redirect_uri) = &self.redirect_uri {
+            form.push(("redirect_uri", redirect_uri));
+        }
+
+        let url = format!("{}/oauth2/token", self.base_url);
+        let response = match ureq::post(&url).send_form(&form) {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                return Err(Error::Auth(format!("Token endpoint returned {}: {}", status, body)));
+            },
+            Err(err) => return Err(Error::Auth(err.to_string())),
+        };
+        let body = response.into_string()
+            .map_err(|err| Error::Auth(err.to_string()))?;
+        let json: json::Value = json::from_str(&body)
+            .map_err(|err| Error::Auth(format!("Invalid token response: {}", err)))?;
+        Token::from_json(&json)
+    }
+}
+
+#[cfg(feature = "http")]
+impl TokenExchange for HttpTokenExchange {
+    fn exchange_code(&self, code: &str) -> Result<Token, Error> {
+        self.request(&[("grant_type", "authorization_code"), ("code", code)])
+    }
+
+    fn refresh(&self, refresh_token: &str) -> Result<Token, Error> {
+        self.request(&[("grant_type", "refresh_token"), ("refresh_token", refresh_token)])
+    }
+}
+
+/// Whether the user is asked for consent again, if they already authorized
+/// the application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Prompt {
+    /// Always ask for consent.
+    Consent,
+    /// Skip asking, if the user already authorized the requested scopes.
+    None,
+}
+
+/// The state of an authenticated connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthSession {
+    /// The `Token` the connection is authenticated with.
+    pub token: Token,
+    /// The scopes the server granted.
+    pub scopes: Vec<String>,
+    /// The authenticated user.
+    pub user: Option<User>,
+}
+
+impl AuthSession {
+    /// Creates an `AuthSession` from the `AUTHENTICATE` response data.
+    fn from_json(token: Token, data: &json::Value) -> Self {
+        let scopes = data["scopes"].as_array()
+            .map(|s| s.iter().filter_map(|s| s.as_str()).map(str::to_string).collect())
+            .unwrap_or_else(|| token.scopes.clone());
+        Self{
+            token,
+            scopes,
+            user: User::from_json(&data["user"]),
+        }
+    }
+}
+
+/// The authentication related state of the client.
+#[derive(Default)]
+pub(crate) struct AuthState {
+    /// The current session, if the connection was authenticated.
+    pub session: Option<AuthSession>,
+    /// The storage the tokens are persisted in, if any.
+    pub store: Option<Arc<dyn TokenStore>>,
+    /// The exchange used to refresh expired tokens when reconnecting, if any.
+    pub exchange: Option<Arc<dyn TokenExchange>>,
+}
+
+impl AuthState {
+    /// Forgets the session and the stored token, because the server revoked
+    /// it.
+    pub fn revoke(&mut self, app_id: &str) {
+        self.session = None;
+        if let Some(store) = &self.store {
+            let _ = store.delete(app_id);
+        }
+    }
+}
+
+impl fmt::Debug for AuthState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthState")
+            .field("session", &self.session)
+            .field("store", &self.store.is_some())
+            .field("exchange", &self.exchange.is_some())
+            .finish()
+    }
+}
+
+impl DiscordRpcHandle {
+    /// Authenticates the connection with the stored token, so the user only
+    /// has to authorize the first time. An expired token is refreshed with
+    /// the `TokenExchange` first. Falls back to `authorize`, if there's no
+    /// usable token for the scopes. Without a `TokenStore` set this is the
+    /// same as `authorize`. If refreshing fails, the error is reported to the
+    /// error handler before falling back. The `TokenExchange` is kept to
+    /// refresh the token, if it expired by the time the connection is
+    /// re-established. Blocks, so in polling mode this must not be called on
+    /// the polling thread.
+    pub fn login(
+        &self,
+        scopes: &[&str],
+        prompt: Prompt,
+        exchange: Arc<dyn TokenExchange>,
+        timeout: Duration,
+    ) -> Result<AuthSession, Error> {
+        let store = {
+            let mut auth = self.shared.auth.lock().unwrap();
+            auth.exchange = Some(exchange.clone());
+            auth.store.clone()
+        };
+        let stored = match &store {
+            Some(store) => match store.load(&self.shared.app_id) {
+                Ok(token) => token,
+                Err(err) => {
+                    // An unreadable token would lock the user out for good,
+                    // report it and start over
+                    self.shared.emit_error(err);
+                    let _ = store.delete(&self.shared.app_id);
+                    None
+                },
+            },
+            None => None,
+        };
+
+        let token = stored.filter(|t| scopes.iter().all(|s| t.scopes.iter().any(|ts| ts == s)));
+        let token = match token {
+            Some(token) if token.is_expired() => match token.refresh_token.as_deref() {
+                Some(refresh_token) => match exchange.refresh(refresh_token) {
+                    Ok(token) => Some(token),
+                    Err(err) => {
+                        // Asking the user again still gets us a token
+                        self.shared.emit_error(err);
+                        None
+                    },
+                },
+                None => None,
+            },
+            token => token,
+        };
+        if let Some(token) = token {
+            match self.authenticate(token, timeout) {
+                Err(Error::Rpc(err)) if err.code == RpcErrorCode::InvalidToken => {
+                    // Fall back to authorizing again
+                },
+                result => return result,
+            }
+        }
+        self.authorize(scopes, prompt, exchange.as_ref(), timeout)
+    }
+
+    /// Runs the whole OAuth2 flow: asks the user to authorize the application
+    /// for the given scopes, exchanges the code with the `TokenExchange` and
+    /// authenticates the connection with the token. Blocks until done, the
+    /// timeout applies to each step waiting for Discord separately, so it
+    /// should leave the user enough time to click. In polling mode this must
+    /// not be called on the polling thread.
+    pub fn authorize(
+        &self,
+        scopes: &[&str],
+        prompt: Prompt,
+        exchange: &dyn TokenExchange,
+        timeout: Duration,
+    ) -> Result<AuthSession, Error> {
+        let mut args = json::json!{{
+            "client_id": self.shared.app_id,
+            "scopes": scopes,
+        }};
+        if prompt == Prompt::None {
+            args["prompt"] = json::Value::String("none".into());
+        }
+
+        let data = self.shared.send(Message::command("AUTHORIZE", args))?
+            .wait_timeout(timeout)?;
+        let code = data["code"].as_str()
+            .ok_or_else(|| Error::Auth("No code in the AUTHORIZE response".into()))?;
+        let token = exchange.exchange_code(code)?;
+        self.authenticate(token, timeout)
+    }
+
+    /// Authenticates the connection with the given `Token`. The session is
+    /// kept and the connection is authenticated again after reconnecting, as
+    /// long as the token doesn't expire. The token is saved to the
+    /// `TokenStore`, if there's one set. Blocks until the server answers, so
+    /// in polling mode this must not be called on the polling thread.
+    pub fn authenticate(&self, token: Token, timeout: Duration) -> Result<AuthSession, Error> {
+        let data = self.shared.send(Message::authenticate(&token.access_token))?
+            .wait_timeout(timeout)?;
+        let session = AuthSession::from_json(token, &data);
+
+        let mut auth = self.shared.auth.lock().unwrap();
+        if let Some(store) = &auth.store {
+            store.save(&self.shared.app_id, &session.token)?;
+        }
+        auth.session = Some(session.clone());
+        Ok(session)
+    }
+
+    /// Gets a new token for the current session with the `TokenExchange` and
+    /// authenticates the connection with it. In polling mode this must not be
+    /// called on the polling thread.
+    pub fn refresh(&self, exchange: &dyn TokenExchange, timeout: Duration) -> Result<AuthSession, Error> {
+        let refresh_token = self.auth_session()
+            .and_then(|s| s.token.refresh_token)
+            .ok_or_else(|| Error::Auth("No refresh token in the current session".into()))?;
+        let token = exchange.refresh(&refresh_token)?;
+        self.authenticate(token, timeout)
+    }
+
+    /// Returns the current `AuthSession`, if the connection was authenticated.
+    pub fn auth_session(&self) -> Option<AuthSession> {
+        self.shared.auth.lock().unwrap().session.clone()
+    }
+}
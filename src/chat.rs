@@ -0,0 +1,70 @@
+//! Chat messages and notifications.
+
+use serde_json as json;
+use crate::User;
+
+/// A message sent in a Discord channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    /// The ID of the message.
+    pub id: String,
+    /// The author of the message, if known.
+    pub author: Option<User>,
+    /// The nickname of the author in the guild, if set.
+    pub nick: Option<String>,
+    /// The text of the message.
+    pub content: String,
+    /// The time the message was sent at, as an ISO 8601 timestamp.
+    pub timestamp: Option<String>,
+    /// The time the message was last edited at, as an ISO 8601 timestamp.
+    pub edited_timestamp: Option<String>,
+    /// The embeds of the message, as Discord sent them.
+    pub embeds: Vec<json::Value>,
+    /// `true`, if the message is pinned.
+    pub pinned: bool,
+}
+
+impl ChatMessage {
+    /// Creates a `ChatMessage` from its JSON representation. Returns `None`,
+    /// if the message has no ID.
+    pub(crate) fn from_json(json: &json::Value) -> Option<Self> {
+        Some(Self{
+            id: json["id"].as_str()?.to_string(),
+            author: User::from_json(&json["author"]),
+            nick: json["nick"].as_str().map(str::to_string),
+            content: json["content"].as_str().unwrap_or_default().to_string(),
+            timestamp: json["timestamp"].as_str().map(str::to_string),
+            edited_timestamp: json["edited_timestamp"].as_str().map(str::to_string),
+            embeds: json["embeds"].as_array().cloned().unwrap_or_default(),
+            pinned: json["pinned"].as_bool().unwrap_or(false),
+        })
+    }
+}
+
+/// A notification the Discord client showed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    /// The ID of the channel the notification is about.
+    pub channel_id: String,
+    /// The message the notification is about, if any.
+    pub message: Option<ChatMessage>,
+    /// The URL of the icon of the notification.
+    pub icon_url: Option<String>,
+    /// The title of the notification.
+    pub title: String,
+    /// The text of the notification.
+    pub body: String,
+}
+
+impl Notification {
+    /// Creates a `Notification` from its JSON representation.
+    pub(crate) fn from_json(json: &json::Value) -> Self {
+        Self{
+            channel_id: json["channel_id"].as_str().unwrap_or_default().to_string(),
+            message: ChatMessage::from_json(&json["message"]),
+            icon_url: json["icon_url"].as_str().map(str::to_string),
+            title: json["title"].as_str().unwrap_or_default().to_string(),
+            body: json["body"].as_str().unwrap_or_default().to_string(),
+        }
+    }
+}
@@ -1,21 +1,88 @@
-//! The RPC client based on a `Connection`.
+//! The low-level, synchronous RPC client based on a `Connection`.
+//!
+//! The `Client` only does the framing and the handshake, everything else is up
+//! to the user: opening it step by step, reading the frames and correlating the
+//! responses by nonce. `DiscordRPC` is a convenience layer on top of it.
 
 use std::fmt;
-use crate::{Connection, IpcConnection, Message, MessageType, Error};
+use std::time::{Duration, Instant};
+use serde_json as json;
+use crate::{Connection, IpcConnection, Message, MessageType, Error, CloseCode, User, nonce};
 
 /// Represents the different states the `Client` can be in.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum State {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    /// There is no connection to the server.
     Disconnected,
-    SentHandshake,
-    Connected,
+    /// The connection is open, but the handshake is not sent yet.
+    Connecting,
+    /// The handshake is sent, waiting for the server to become ready.
+    HandshakeSent,
+    /// The server is ready to receive commands.
+    Ready,
+}
+
+/// The information the server sends when it becomes ready.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadyInfo {
+    /// The RPC version of the server.
+    pub version: u32,
+    /// The currently logged in user.
+    pub user: Option<User>,
+    /// The host of the Discord CDN.
+    pub cdn_host: String,
+    /// The endpoint of the Discord API.
+    pub api_endpoint: String,
+    /// The environment of the Discord client, like `production`.
+    pub environment: String,
+}
+
+impl ReadyInfo {
+    /// Creates a `ReadyInfo` from the data of a `READY` event.
+    pub(crate) fn from_json(data: &json::Value) -> Self {
+        let config = &data["config"];
+        Self{
+            version: data["v"].as_u64().unwrap_or(1) as u32,
+            user: User::from_json(&data["user"]),
+            cdn_host: config["cdn_host"].as_str().unwrap_or_default().to_string(),
+            api_endpoint: config["api_endpoint"].as_str().unwrap_or_default().to_string(),
+            environment: config["environment"].as_str().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+/// The settings of the client-initiated Ping keepalive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeepAlive {
+    /// The time between two Pings.
+    pub interval: Duration,
+    /// The time to wait for a Pong or any other frame after a Ping, before the
+    /// connection is considered dead.
+    pub timeout: Duration,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self{
+            interval: Duration::from_secs(15),
+            timeout: Duration::from_secs(5),
+        }
+    }
 }
 
 /// Represents an RPC client with a `Connection`.
 pub struct Client {
     connection: Box<dyn Connection>,
-    state: State,
+    state: ConnectionState,
     app_id: String,
+    close_code: Option<CloseCode>,
+    ready_info: Option<ReadyInfo>,
+    // Keepalive
+    keepalive: Option<KeepAlive>,
+    last_ping: Option<Instant>,
+    ping_sent: Option<Instant>,
+    last_received: Option<Instant>,
+    latency: Option<Duration>,
     // Event handlers
     on_connect: Box<dyn Fn() + Send>,
     on_error: Box<dyn Fn(Error) + Send>,
@@ -24,11 +91,19 @@ pub struct Client {
 
 impl Client {
     /// Creates a new `Client` with the given `Connection` and application ID.
-    pub fn with_connection<C: Connection>(connection: C, app_id: &str) -> Self where C: 'static {
+    pub fn with_connection<C: Connection + 'static>(connection: C, app_id: &str) -> Self {
         Self{
             connection: Box::new(connection),
-            state: State::Disconnected,
+            state: ConnectionState::Disconnected,
             app_id: app_id.to_string(),
+            close_code: None,
+            ready_info: None,
+
+            keepalive: None,
+            last_ping: None,
+            ping_sent: None,
+            last_received: None,
+            latency: None,
 
             on_connect: Box::new(|| {}),
             on_error: Box::new(|_| {}),
@@ -42,119 +117,269 @@ impl Client {
         Self::with_connection(IpcConnection::new(), app_id)
     }
 
+    /// Sets the handler that is called when the handshake succeeded.
+    pub fn set_on_connect<F>(&mut self, f: F) where F: Fn() + Send + 'static {
+        self.on_connect = Box::new(f);
+    }
+
+    /// Sets the handler that is called when an error occurs.
+    pub fn set_on_error<F>(&mut self, f: F) where F: Fn(Error) + Send + 'static {
+        self.on_error = Box::new(f);
+    }
+
+    /// Sets the handler that is called when the connection is closed.
+    pub fn set_on_disconnect<F>(&mut self, f: F) where F: Fn() + Send + 'static {
+        self.on_disconnect = Box::new(f);
+    }
+
+    /// Returns the application ID of the `Client`.
+    pub fn app_id(&self) -> &str {
+        &self.app_id
+    }
+
     /// Returns `true`, if the communication is alive.
     pub fn is_open(&self) -> bool {
-        self.state == State::Connected
+        self.state == ConnectionState::Ready
     }
 
-    /// Opens the `Client` for communication.
-    pub fn open(&mut self) {
-        if self.state == State::Connected {
-            return;
-        }
+    /// Returns the current `ConnectionState` of the `Client`.
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
 
-        if self.state == State::Disconnected && !self.connection.open() {
-            return;
-        }
+    /// Returns the `ReadyInfo` the server sent at the last successful
+    /// handshake.
+    pub fn ready_info(&self) -> Option<&ReadyInfo> {
+        self.ready_info.as_ref()
+    }
 
-        if self.state == State::SentHandshake {
-            if let Some(message) = self.read() {
-                let cmd = message.value("cmd");
-                let evt = message.value("evt");
-                if cmd == Some("DISPATCH") && evt == Some("READY") {
-                    self.state = State::Connected;
-                    (self.on_connect)();
-                }
-            }
-        }
-        else {
-            // Send handshake
-            let handshake = Message::new(MessageType::Handshake, serde_json::json!{{
-                "v": 1,
-                "client_id": self.app_id,
-            }});
-            if self.write(handshake) {
-                self.state = State::SentHandshake;
-            }
-            else {
-                self.close();
+    /// Sets the `KeepAlive` settings, `None` disables the keepalive. It is
+    /// disabled by default.
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepAlive>) {
+        self.keepalive = keepalive;
+    }
+
+    /// Returns the round-trip time measured by the last answered Ping.
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// Returns the `CloseCode` the server last closed the connection with, if
+    /// it did so since the last successful handshake.
+    pub fn close_code(&self) -> Option<CloseCode> {
+        self.close_code
+    }
+
+    /// Opens the `Client` for communication without blocking. Every call
+    /// advances the opening as far as possible with the data available, so
+    /// this has to be called until it returns `ConnectionState::Ready`.
+    pub fn open(&mut self) -> Result<ConnectionState, Error> {
+        loop {
+            match self.state {
+                ConnectionState::Ready => break,
+                ConnectionState::Disconnected => {
+                    if !self.connection.open() {
+                        return Err(Error::PipeClosed("Could not connect to the server".into()));
+                    }
+                    self.state = ConnectionState::Connecting;
+                },
+                ConnectionState::Connecting => {
+                    // Send handshake
+                    let handshake = Message::handshake(&self.app_id);
+                    if let Err(err) = self.write(&handshake) {
+                        self.close();
+                        return Err(err);
+                    }
+                    self.state = ConnectionState::HandshakeSent;
+                },
+                ConnectionState::HandshakeSent => {
+                    // Nothing more to do until the server answers
+                    let message = match self.read()? {
+                        Some(message) => message,
+                        None => break,
+                    };
+                    let cmd = message.value("cmd");
+                    let evt = message.value("evt");
+                    if cmd == Some("DISPATCH") && evt == Some("READY") {
+                        self.state = ConnectionState::Ready;
+                        self.close_code = None;
+                        self.ready_info = Some(ReadyInfo::from_json(&message.payload()["data"]));
+                        self.last_ping = Some(Instant::now());
+                        self.ping_sent = None;
+                        (self.on_connect)();
+                    }
+                },
             }
         }
+        Ok(self.state)
     }
 
     /// Closes the `Client` from further communication.
     pub fn close(&mut self) {
-        if self.state == State::Connected || self.state == State::SentHandshake {
+        if self.state != ConnectionState::Disconnected {
             (self.on_disconnect)();
         }
         self.connection.close();
-        self.state = State::Disconnected;
+        self.state = ConnectionState::Disconnected;
+    }
+
+    /// Tells the server that we are leaving with a Close frame, then closes
+    /// the `Client`.
+    pub fn disconnect(&mut self) {
+        if self.state != ConnectionState::Disconnected {
+            let close = Message::new(MessageType::Close, json::json!{{
+                "code": i32::from(CloseCode::Normal),
+                "message": "Client shutting down",
+            }});
+            // We are closing anyway, no matter if it went through
+            let _ = self.write(&close);
+        }
+        self.close();
     }
 
-    /// Tries to read a `Message` from the server.
-    pub fn read(&mut self) -> Option<Message> {
-        if self.state != State::Connected && self.state != State::SentHandshake {
-            return None;
+    /// Tries to read a `Message` from the server without blocking. Returns
+    /// `Ok(None)`, if there's no complete `Message` available. Pings are
+    /// answered here, only frames are returned. On errors the `Client` is
+    /// closed.
+    pub fn read(&mut self) -> Result<Option<Message>, Error> {
+        if self.state == ConnectionState::Disconnected {
+            return Err(Error::NotConnected);
+        }
+        if self.state == ConnectionState::Connecting {
+            return Ok(None);
         }
 
         loop {
-            let message = Message::decode_from(self.connection.as_mut());
-            if message.is_err() {
-                let err = message.unwrap_err();
-                (self.on_error)(err);
-                self.close();
-                return None;
-            }
+            let message = match Message::decode_from(self.connection.as_mut()) {
+                Ok(message) => message,
+                Err(err) => return Err(self.fail(err)),
+            };
 
-            let message = message.unwrap();
             if let Some(mut message) = message {
+                let now = Instant::now();
+                self.last_received = Some(now);
                 match message.ty() {
                     MessageType::Close => {
                         // Forced by server, read description, send error
-                        let code = message.value("code")
-                            .and_then(|s| s.parse::<i32>().ok()).unwrap_or(0);
-                        let message = message.value("message").unwrap_or("<none>").to_string();
-                        (self.on_error)(Error::ConnectionClosed{ code, message });
-                        self.close();
-                        return None;
+                        let err = Error::connection_closed(&message);
+                        if let Error::ConnectionClosed{ code, .. } = &err {
+                            self.close_code = Some(*code);
+                        }
+                        return Err(self.fail(err));
                     },
                     MessageType::Frame => {
-                        return Some(message);
+                        return Ok(Some(message));
                     },
                     MessageType::Ping => {
                         // Send pong
                         message.set_ty(MessageType::Pong);
-                        if !self.write(message) {
+                        if let Err(err) = self.write(&message) {
                             // If we couldn't send Pong, close
-                            self.close();
+                            return Err(self.fail(err));
                         }
                     },
                     MessageType::Pong => {
-                        // No-op
+                        if let Some(sent) = self.ping_sent.take() {
+                            self.latency = Some(now - sent);
+                        }
                     },
                     x => {
                         // Any other message type is invalid here
-                        (self.on_error)(Error::InvalidMessage(format!(
-                            "Message of type {:?} can't be sent by the server!", x)));
-                        self.close();
-                        return None;
+                        return Err(self.fail(Error::InvalidMessage(format!(
+                            "Message of type {:?} can't be sent by the server!", x))));
                     },
                 }
             }
             else {
                 if !self.connection.is_open() {
                     // TODO: Can we get the reason?
-                    (self.on_error)(Error::PipeClosed("Unknown reason".into()));
-                    self.close();
+                    return Err(self.fail(Error::PipeClosed("Unknown reason".into())));
                 }
-                return None;
+                return Ok(None);
             }
         }
     }
 
-    /// Tries to write a `Message` to the server. Returns `false` on failure.
-    pub fn write(&mut self, message: Message) -> bool {
-        message.encode_to(self.connection.as_mut())
+    /// Tries to write a `Message` to the server.
+    pub fn write(&mut self, message: &Message) -> Result<(), Error> {
+        if self.state == ConnectionState::Disconnected {
+            return Err(Error::NotConnected);
+        }
+        if !message.encode_to(self.connection.as_mut()) {
+            return Err(Error::PipeClosed("Could not write the message".into()));
+        }
+        Ok(())
+    }
+
+    /// Sends the command `cmd` with the given arguments to the server. The
+    /// `Client` must be ready. Returns the nonce of the command, the response
+    /// will be a frame read with the same nonce.
+    pub fn send_command(&mut self, cmd: &str, args: json::Value) -> Result<String, Error> {
+        if self.state != ConnectionState::Ready {
+            return Err(Error::NotConnected);
+        }
+        let message = Message::command(cmd, args);
+        self.write(&message)?;
+        Ok(message.value("nonce").unwrap_or_default().to_string())
+    }
+
+    /// Sends a Ping when it's due and closes the `Client` with
+    /// `Error::KeepAliveTimeout`, if nothing arrived in time since the last one. Does
+    /// nothing, if the keepalive is disabled or the `Client` isn't ready. Has
+    /// to be called periodically.
+    pub fn keepalive(&mut self) -> Result<(), Error> {
+        let keepalive = match self.keepalive {
+            Some(keepalive) if self.state == ConnectionState::Ready => keepalive,
+            _ => return Ok(()),
+        };
+
+        let now = Instant::now();
+        // Did anything arrive since we pinged?
+        let answered = |sent: Instant| self.last_received.is_some_and(|r| r >= sent);
+        if let Some(sent) = self.ping_sent {
+            if !answered(sent) && now - sent >= keepalive.timeout {
+                return Err(self.fail(Error::KeepAliveTimeout));
+            }
+        }
+
+        let ping_due = self.last_ping.is_none_or(|p| now - p >= keepalive.interval);
+        if ping_due && self.ping_sent.is_none_or(answered) {
+            let ping = Message::new(MessageType::Ping, json::json!{{
+                "nonce": nonce(),
+            }});
+            if let Err(err) = self.write(&ping) {
+                return Err(self.fail(err));
+            }
+            self.last_ping = Some(now);
+            self.ping_sent = Some(now);
+        }
+        Ok(())
+    }
+
+    /// Returns `true`, if a Ping is waiting for its Pong. The latency is only
+    /// as precise as often `read` is called meanwhile.
+    pub fn awaiting_pong(&self) -> bool {
+        match (self.keepalive, self.ping_sent) {
+            (Some(keepalive), Some(sent)) => sent.elapsed() < keepalive.timeout,
+            _ => false,
+        }
+    }
+
+    /// Returns the time the next Ping is due, if the keepalive is enabled and
+    /// the `Client` is ready.
+    pub fn next_ping(&self) -> Option<Instant> {
+        match self.keepalive {
+            Some(keepalive) if self.state == ConnectionState::Ready =>
+                Some(self.last_ping.map_or_else(Instant::now, |p| p + keepalive.interval)),
+            _ => None,
+        }
+    }
+
+    /// Reports the error to the error handler, then closes the `Client`.
+    fn fail(&mut self, err: Error) -> Error {
+        (self.on_error)(err.clone());
+        self.close();
+        err
     }
 }
 
@@ -170,6 +395,8 @@ impl fmt::Debug for Client {
         f.debug_struct("Client")
             .field("state", &self.state)
             .field("app_id", &self.app_id)
+            .field("close_code", &self.close_code)
+            .field("latency", &self.latency)
             .finish()
     }
 }
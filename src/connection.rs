@@ -4,7 +4,8 @@
 /// abstraction point for IPC and other communication methods between platforms.
 pub trait Connection: Send {
     /// Tries to open a connection to a Discord RPC server. Returns `true`, if
-    /// the connection could be established.
+    /// the connection could be established. Must not block waiting for the
+    /// server, if it's busy the call simply fails and is retried later.
     fn open(&mut self) -> bool;
 
     /// Returns `true`, if the `Connection` is currently open.
@@ -26,3 +27,20 @@ pub trait Connection: Send {
 
 #[cfg(target_os = "windows")]
 pub type IpcConnection = crate::windows::NamedPipe;
+
+#[cfg(unix)]
+pub type IpcConnection = crate::unix::UnixSocket;
+
+/// The asynchronous counterpart of `Connection`. Opening it yields a stream
+/// that the `AsyncDiscordRpc` reads and writes frames on.
+#[cfg(feature = "tokio")]
+pub trait AsyncConnection: Send {
+    /// The stream type an open connection is represented by.
+    type Stream: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static;
+
+    /// Tries to open a connection to a Discord RPC server.
+    fn open(&mut self) -> impl std::future::Future<Output = std::io::Result<Self::Stream>> + Send;
+}
+
+#[cfg(all(unix, feature = "tokio"))]
+pub type AsyncIpcConnection = crate::unix::AsyncUnixSocket;
@@ -0,0 +1,129 @@
+//! Reporting certified hardware devices to Discord.
+
+use serde_json as json;
+use crate::{DiscordRpcHandle, Message, Response, Error};
+
+/// The type of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceType {
+    /// An audio input device, like a microphone.
+    AudioInput,
+    /// An audio output device, like headphones.
+    AudioOutput,
+    /// A video input device, like a camera.
+    VideoInput,
+}
+
+impl DeviceType {
+    /// Returns the name of the device type in the RPC protocol.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::AudioInput => "audioinput",
+            Self::AudioOutput => "audiooutput",
+            Self::VideoInput => "videoinput",
+        }
+    }
+}
+
+/// The vendor or the model of a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// The name of the vendor or model.
+    pub name: String,
+    /// The URL of the vendor or model.
+    pub url: String,
+}
+
+/// A device certified by Discord, that the application reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertifiedDevice {
+    /// The type of the device.
+    pub ty: DeviceType,
+    /// The Windows UUID of the device.
+    pub id: String,
+    /// The vendor of the device.
+    pub vendor: DeviceInfo,
+    /// The model of the device.
+    pub model: DeviceInfo,
+    /// The UUIDs of the related devices.
+    pub related: Vec<String>,
+    /// `true`, if the device does echo cancellation. Only for audio inputs.
+    pub echo_cancellation: Option<bool>,
+    /// `true`, if the device does noise suppression. Only for audio inputs.
+    pub noise_suppression: Option<bool>,
+    /// `true`, if the device does automatic gain control. Only for audio
+    /// inputs.
+    pub automatic_gain_control: Option<bool>,
+    /// `true`, if the device is muted in hardware. Only for audio inputs.
+    pub hardware_mute: Option<bool>,
+}
+
+impl CertifiedDevice {
+    /// Checks that the required fields are filled and the audio input
+    /// features are only set for audio inputs.
+    fn validate(&self) -> Result<(), Error> {
+        let required = [
+            ("id", &self.id),
+            ("vendor name", &self.vendor.name),
+            ("vendor URL", &self.vendor.url),
+            ("model name", &self.model.name),
+            ("model URL", &self.model.url),
+        ];
+        for (name, value) in required {
+            if value.is_empty() {
+                return Err(Error::InvalidArgument(format!("The {} of the device is missing", name)));
+            }
+        }
+        let has_input_features = self.echo_cancellation.is_some()
+            || self.noise_suppression.is_some()
+            || self.automatic_gain_control.is_some()
+            || self.hardware_mute.is_some();
+        if has_input_features && self.ty != DeviceType::AudioInput {
+            return Err(Error::InvalidArgument(format!(
+                "Device {} has audio input features, but is not an audio input", self.id)));
+        }
+        Ok(())
+    }
+
+    /// Returns the JSON representation of the device.
+    fn to_json(&self) -> json::Value {
+        let mut json = json::json!{{
+            "type": self.ty.as_str(),
+            "id": self.id,
+            "vendor": { "name": self.vendor.name, "url": self.vendor.url },
+            "model": { "name": self.model.name, "url": self.model.url },
+            "related": self.related,
+        }};
+        let features = [
+            ("echo_cancellation", self.echo_cancellation),
+            ("noise_suppression", self.noise_suppression),
+            ("automatic_gain_control", self.automatic_gain_control),
+            ("hardware_mute", self.hardware_mute),
+        ];
+        for (key, value) in features {
+            if let Some(value) = value {
+                json[key] = value.into();
+            }
+        }
+        json
+    }
+}
+
+/// Creates the `SET_CERTIFIED_DEVICES` `Message` for the given devices.
+pub(crate) fn certified_devices_message(devices: &[CertifiedDevice]) -> Message {
+    let devices: Vec<json::Value> = devices.iter().map(CertifiedDevice::to_json).collect();
+    Message::command("SET_CERTIFIED_DEVICES", json::json!{{ "devices": devices }})
+}
+
+impl DiscordRpcHandle {
+    /// Reports the certified devices connected to the computer, replacing the
+    /// previous list. The list is sent again after reconnecting.
+    pub fn set_certified_devices(&self, devices: Vec<CertifiedDevice>) -> Result<Response, Error> {
+        for device in &devices {
+            device.validate()?;
+        }
+        let response = self.shared.send(certified_devices_message(&devices))?;
+        *self.shared.certified_devices.lock().unwrap() = Some(devices);
+        Ok(response)
+    }
+}
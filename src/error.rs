@@ -2,19 +2,58 @@
 
 use std::fmt;
 use std::error;
+use crate::Message;
 
 /// Errors during the communication.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Error {
     /// The pipe was closed to the RPC server.
     PipeClosed(String),
     /// The connection was closed by the RPC server.
     ConnectionClosed{
-        code: i32,
+        code: CloseCode,
         message: String,
     },
     /// An invalid message type was sent by the server.
     InvalidMessage(String),
+    /// The RPC server rejected a command.
+    Rpc(RpcError),
+    /// No response arrived in time.
+    Timeout,
+    /// The server didn't answer the keepalive Ping in time, the connection is
+    /// considered dead.
+    KeepAliveTimeout,
+    /// The message didn't fit into the send queue.
+    QueueFull,
+    /// The client is not connected, or not ready for the operation.
+    NotConnected,
+    /// The OAuth2 flow failed.
+    Auth(String),
+    /// Storing or loading a token failed.
+    Storage(String),
+    /// An argument of a command is out of its valid range.
+    InvalidArgument(String),
+    /// Selecting a channel didn't finish in time.
+    SelectChannelTimedOut,
+    /// The user is already in a voice channel, selecting another one needs
+    /// `force`.
+    SelectVoiceForceRequired,
+    /// Another keyboard shortcut capture is already running.
+    CaptureShortcutAlreadyListening,
+    /// The client was shut down, or the IO thread abandoned by a previous
+    /// shutdown is still finishing.
+    ShuttingDown,
+}
+
+impl Error {
+    /// Creates a `ConnectionClosed` error from a Close `Message`.
+    pub(crate) fn connection_closed(message: &Message) -> Self {
+        let code = message.payload()["code"].as_i64()
+            .map(|c| CloseCode::from(c as i32))
+            .unwrap_or(CloseCode::Unknown(0));
+        let message = message.value("message").unwrap_or("<none>").to_string();
+        Self::ConnectionClosed{ code, message }
+    }
 }
 
 impl fmt::Display for Error {
@@ -26,8 +65,341 @@ impl fmt::Display for Error {
                 write!(f, "Connection forced to close by server (code: {}): {}", code, message),
             Self::InvalidMessage(desc) =>
                 write!(f, "Invalid message read: {}", desc),
+            Self::Rpc(err) =>
+                write!(f, "{}", err),
+            Self::Timeout =>
+                write!(f, "Timed out waiting for a response"),
+            Self::KeepAliveTimeout =>
+                write!(f, "The server didn't answer the keepalive Ping in time"),
+            Self::QueueFull =>
+                write!(f, "The send queue is full"),
+            Self::NotConnected =>
+                write!(f, "Not connected to the server"),
+            Self::Auth(desc) =>
+                write!(f, "Authorization failed: {}", desc),
+            Self::Storage(desc) =>
+                write!(f, "Token storage failed: {}", desc),
+            Self::InvalidArgument(desc) =>
+                write!(f, "Invalid argument: {}", desc),
+            Self::SelectChannelTimedOut =>
+                write!(f, "Selecting the channel timed out"),
+            Self::SelectVoiceForceRequired =>
+                write!(f, "The user is already in a voice channel"),
+            Self::CaptureShortcutAlreadyListening =>
+                write!(f, "A shortcut capture is already running"),
+            Self::ShuttingDown =>
+                write!(f, "The client is shutting down"),
         }
     }
 }
 
 impl error::Error for Error {}
+
+/// The codes the RPC server can close the connection with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseCode {
+    /// The connection was closed normally.
+    Normal,
+    /// The application ID sent in the handshake is invalid.
+    InvalidClientId,
+    /// The origin of the connection is not allowed for the application.
+    InvalidOrigin,
+    /// The client is sending too many messages.
+    RateLimited,
+    /// The OAuth2 token of the connection was revoked.
+    TokenRevoked,
+    /// The RPC version sent in the handshake is not supported.
+    InvalidVersion,
+    /// The requested encoding is not supported.
+    InvalidEncoding,
+    /// Any other code we don't know about.
+    Unknown(i32),
+}
+
+impl CloseCode {
+    /// Returns `true`, if it makes sense to try reconnecting after the server
+    /// closed the connection with this code. Codes that mean the handshake
+    /// itself is wrong would just be rejected again.
+    pub fn should_reconnect(self) -> bool {
+        !matches!(self,
+            Self::InvalidClientId
+            | Self::InvalidOrigin
+            | Self::InvalidVersion
+            | Self::InvalidEncoding)
+    }
+}
+
+impl From<CloseCode> for i32 {
+    fn from(code: CloseCode) -> i32 {
+        match code {
+            CloseCode::Normal => 1000,
+            CloseCode::InvalidClientId => 4000,
+            CloseCode::InvalidOrigin => 4001,
+            CloseCode::RateLimited => 4002,
+            CloseCode::TokenRevoked => 4003,
+            CloseCode::InvalidVersion => 4004,
+            CloseCode::InvalidEncoding => 4005,
+            CloseCode::Unknown(x) => x,
+        }
+    }
+}
+
+impl From<i32> for CloseCode {
+    fn from(n: i32) -> Self {
+        match n {
+            1000 => Self::Normal,
+            4000 => Self::InvalidClientId,
+            4001 => Self::InvalidOrigin,
+            4002 => Self::RateLimited,
+            4003 => Self::TokenRevoked,
+            4004 => Self::InvalidVersion,
+            4005 => Self::InvalidEncoding,
+            x => Self::Unknown(x),
+        }
+    }
+}
+
+impl fmt::Display for CloseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code: i32 = (*self).into();
+        match self {
+            Self::Unknown(_) => write!(f, "{}", code),
+            x => write!(f, "{} ({:?})", code, x),
+        }
+    }
+}
+
+/// The error codes the RPC server reports in `ERROR` events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RpcErrorCode {
+    /// An unknown error occurred.
+    UnknownError,
+    /// The service is unavailable.
+    ServiceUnavailable,
+    /// The transaction was aborted.
+    TransactionAborted,
+    /// The payload of the command was invalid.
+    InvalidPayload,
+    /// The command name was invalid.
+    InvalidCommand,
+    /// The guild ID was invalid.
+    InvalidGuild,
+    /// The event name was invalid.
+    InvalidEvent,
+    /// The channel ID was invalid.
+    InvalidChannel,
+    /// The client doesn't have permission for the command.
+    InvalidPermissions,
+    /// The client ID was invalid.
+    InvalidClientId,
+    /// The origin of the connection was invalid.
+    InvalidOrigin,
+    /// The OAuth2 token was invalid.
+    InvalidToken,
+    /// The user ID was invalid.
+    InvalidUser,
+    /// The invite was invalid.
+    InvalidInvite,
+    /// The activity join request was invalid.
+    InvalidActivityJoinRequest,
+    /// The lobby ID was invalid.
+    InvalidLobby,
+    /// The lobby secret was invalid.
+    InvalidLobbySecret,
+    /// The entitlement was invalid.
+    InvalidEntitlement,
+    /// The gift code was invalid.
+    InvalidGiftCode,
+    /// A generic OAuth2 error.
+    OAuth2Error,
+    /// Selecting a channel took too long.
+    SelectChannelTimedOut,
+    /// Fetching a guild took too long.
+    GetGuildTimedOut,
+    /// The user is already in a voice channel, `force` is needed to move them.
+    SelectVoiceForceRequired,
+    /// A keyboard shortcut capture is already in progress.
+    CaptureShortcutAlreadyListening,
+    /// The activity secret was invalid.
+    InvalidActivitySecret,
+    /// The user has no activity that could be joined.
+    NoEligibleActivity,
+    /// The lobby is full.
+    LobbyFull,
+    /// The user canceled the purchase.
+    PurchaseCanceled,
+    /// The purchase failed.
+    PurchaseError,
+    /// The application isn't authorized to update the achievement.
+    UnauthorizedForAchievement,
+    /// Any other code we don't know about.
+    Unknown(i32),
+}
+
+impl From<RpcErrorCode> for i32 {
+    fn from(code: RpcErrorCode) -> i32 {
+        match code {
+            RpcErrorCode::UnknownError => 1000,
+            RpcErrorCode::ServiceUnavailable => 1001,
+            RpcErrorCode::TransactionAborted => 1002,
+            RpcErrorCode::InvalidPayload => 4000,
+            RpcErrorCode::InvalidCommand => 4002,
+            RpcErrorCode::InvalidGuild => 4003,
+            RpcErrorCode::InvalidEvent => 4004,
+            RpcErrorCode::InvalidChannel => 4005,
+            RpcErrorCode::InvalidPermissions => 4006,
+            RpcErrorCode::InvalidClientId => 4007,
+            RpcErrorCode::InvalidOrigin => 4008,
+            RpcErrorCode::InvalidToken => 4009,
+            RpcErrorCode::InvalidUser => 4010,
+            RpcErrorCode::InvalidInvite => 4011,
+            RpcErrorCode::InvalidActivityJoinRequest => 4012,
+            RpcErrorCode::InvalidLobby => 4013,
+            RpcErrorCode::InvalidLobbySecret => 4014,
+            RpcErrorCode::InvalidEntitlement => 4015,
+            RpcErrorCode::InvalidGiftCode => 4016,
+            RpcErrorCode::OAuth2Error => 5000,
+            RpcErrorCode::SelectChannelTimedOut => 5001,
+            RpcErrorCode::GetGuildTimedOut => 5002,
+            RpcErrorCode::SelectVoiceForceRequired => 5003,
+            RpcErrorCode::CaptureShortcutAlreadyListening => 5004,
+            RpcErrorCode::InvalidActivitySecret => 5005,
+            RpcErrorCode::NoEligibleActivity => 5006,
+            RpcErrorCode::LobbyFull => 5007,
+            RpcErrorCode::PurchaseCanceled => 5008,
+            RpcErrorCode::PurchaseError => 5009,
+            RpcErrorCode::UnauthorizedForAchievement => 5010,
+            RpcErrorCode::Unknown(x) => x,
+        }
+    }
+}
+
+impl From<i32> for RpcErrorCode {
+    fn from(n: i32) -> Self {
+        match n {
+            1000 => Self::UnknownError,
+            1001 => Self::ServiceUnavailable,
+            1002 => Self::TransactionAborted,
+            4000 => Self::InvalidPayload,
+            4002 => Self::InvalidCommand,
+            4003 => Self::InvalidGuild,
+            4004 => Self::InvalidEvent,
+            4005 => Self::InvalidChannel,
+            4006 => Self::InvalidPermissions,
+            4007 => Self::InvalidClientId,
+            4008 => Self::InvalidOrigin,
+            4009 => Self::InvalidToken,
+            4010 => Self::InvalidUser,
+            4011 => Self::InvalidInvite,
+            4012 => Self::InvalidActivityJoinRequest,
+            4013 => Self::InvalidLobby,
+            4014 => Self::InvalidLobbySecret,
+            4015 => Self::InvalidEntitlement,
+            4016 => Self::InvalidGiftCode,
+            5000 => Self::OAuth2Error,
+            5001 => Self::SelectChannelTimedOut,
+            5002 => Self::GetGuildTimedOut,
+            5003 => Self::SelectVoiceForceRequired,
+            5004 => Self::CaptureShortcutAlreadyListening,
+            5005 => Self::InvalidActivitySecret,
+            5006 => Self::NoEligibleActivity,
+            5007 => Self::LobbyFull,
+            5008 => Self::PurchaseCanceled,
+            5009 => Self::PurchaseError,
+            5010 => Self::UnauthorizedForAchievement,
+            x => Self::Unknown(x),
+        }
+    }
+}
+
+/// An error reported by the RPC server for a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+    /// The error code.
+    pub code: RpcErrorCode,
+    /// The human-readable description sent by the server.
+    pub message: String,
+    /// The nonce of the command that caused the error, if known.
+    pub nonce: Option<String>,
+    /// The command that caused the error, if known.
+    pub cmd: Option<String>,
+}
+
+impl RpcError {
+    /// Creates an `RpcError` from an `ERROR` event `Message`.
+    pub(crate) fn from_message(message: &Message) -> Self {
+        let data = &message.payload()["data"];
+        Self{
+            code: data["code"].as_i64().map(|c| RpcErrorCode::from(c as i32))
+                .unwrap_or(RpcErrorCode::UnknownError),
+            message: data["message"].as_str().unwrap_or("<none>").to_string(),
+            nonce: message.value("nonce").map(str::to_string),
+            cmd: message.value("cmd").map(str::to_string),
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code: i32 = self.code.into();
+        write!(f, "RPC error (code: {}", code)?;
+        if let Some(cmd) = &self.cmd {
+            write!(f, ", command: {}", cmd)?;
+        }
+        write!(f, "): {}", self.message)
+    }
+}
+
+impl error::Error for RpcError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageType;
+
+    #[test]
+    fn close_code_round_trip() {
+        for n in [1000, 4000, 4001, 4002, 4003, 4004, 4005, 4999] {
+            assert_eq!(i32::from(CloseCode::from(n)), n);
+        }
+        assert_eq!(CloseCode::from(4002), CloseCode::RateLimited);
+        assert_eq!(CloseCode::from(4999), CloseCode::Unknown(4999));
+    }
+
+    #[test]
+    fn close_code_reconnect() {
+        assert!(CloseCode::Normal.should_reconnect());
+        assert!(CloseCode::RateLimited.should_reconnect());
+        assert!(!CloseCode::InvalidClientId.should_reconnect());
+        assert!(!CloseCode::InvalidVersion.should_reconnect());
+    }
+
+    #[test]
+    fn rpc_error_code_round_trip() {
+        let known = (1000..=1002).chain(Some(4000)).chain(4002..=4016).chain(5000..=5010);
+        for n in known {
+            let code = RpcErrorCode::from(n);
+            assert!(!matches!(code, RpcErrorCode::Unknown(_)), "{}", n);
+            assert_eq!(i32::from(code), n);
+        }
+        assert_eq!(RpcErrorCode::from(5003), RpcErrorCode::SelectVoiceForceRequired);
+        assert_eq!(RpcErrorCode::from(4013), RpcErrorCode::InvalidLobby);
+        assert_eq!(i32::from(RpcErrorCode::from(4001)), 4001);
+        assert_eq!(RpcErrorCode::from(4001), RpcErrorCode::Unknown(4001));
+    }
+
+    #[test]
+    fn rpc_error_from_message() {
+        let message = Message::new(MessageType::Frame, serde_json::json!{{
+            "cmd": "SELECT_VOICE_CHANNEL",
+            "evt": "ERROR",
+            "nonce": "42",
+            "data": { "code": 5003, "message": "Force required" },
+        }});
+        let err = RpcError::from_message(&message);
+        assert_eq!(err.code, RpcErrorCode::SelectVoiceForceRequired);
+        assert_eq!(err.message, "Force required");
+        assert_eq!(err.nonce.as_deref(), Some("42"));
+        assert_eq!(err.cmd.as_deref(), Some("SELECT_VOICE_CHANNEL"));
+    }
+}
@@ -0,0 +1,223 @@
+//! Events dispatched by the Discord RPC server.
+
+use serde_json as json;
+use crate::{Message, User, ShortcutKey, ActivityInvite, OverlayState, Lobby, LobbyMember, Guild, Channel, ChatMessage, Notification, VoiceState, VoiceSettings, Error, RpcError};
+
+/// The status of the voice connection of the user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceConnectionStatus {
+    /// The state of the connection, like `CONNECTED` or `NO_ROUTE`.
+    pub state: String,
+    /// The hostname of the voice server, if connected.
+    pub hostname: Option<String>,
+    /// The last pings in milliseconds.
+    pub pings: Vec<f64>,
+    /// The average ping in milliseconds.
+    pub average_ping: Option<f64>,
+    /// The last ping in milliseconds.
+    pub last_ping: Option<f64>,
+}
+
+impl VoiceConnectionStatus {
+    /// Creates a `VoiceConnectionStatus` from its JSON representation.
+    fn from_json(json: &json::Value) -> Self {
+        Self{
+            state: json["state"].as_str().unwrap_or_default().to_string(),
+            hostname: json["hostname"].as_str().map(str::to_string),
+            pings: json["pings"].as_array()
+                .map(|p| p.iter().filter_map(|p| p["value"].as_f64().or_else(|| p.as_f64())).collect())
+                .unwrap_or_default(),
+            average_ping: json["average_ping"].as_f64(),
+            last_ping: json["last_ping"].as_f64(),
+        }
+    }
+}
+
+/// An event dispatched by the Discord RPC server.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The server reported an error.
+    Error(Error),
+    /// The online count of a subscribed guild changed.
+    GuildStatus{
+        guild: Guild,
+        online: u32,
+    },
+    /// The user joined a guild.
+    GuildCreate(Guild),
+    /// A channel was created in a guild of the user.
+    ChannelCreate(Channel),
+    /// The user changed.
+    CurrentUserUpdate(User),
+    /// The user joined the activity of someone else in Discord, the game
+    /// should connect with the join secret.
+    ActivityJoin{
+        secret: String,
+    },
+    /// The user started spectating the activity of someone else in Discord,
+    /// the game should connect with the spectate secret.
+    ActivitySpectate{
+        secret: String,
+    },
+    /// Someone asked to join the activity of the user.
+    ActivityJoinRequest(User),
+    /// The user was invited to the activity of someone else.
+    ActivityInvite(ActivityInvite),
+    /// The overlay was opened or closed in the game.
+    Overlay(OverlayState),
+    /// The state of the overlay changed in the game.
+    OverlayUpdate(OverlayState),
+    /// A subscribed lobby changed.
+    LobbyUpdate(Lobby),
+    /// A subscribed lobby was deleted.
+    LobbyDelete{
+        lobby_id: String,
+        reason: i32,
+    },
+    /// A member connected to a subscribed lobby.
+    LobbyMemberConnect{
+        lobby_id: String,
+        member: LobbyMember,
+    },
+    /// A member of a subscribed lobby changed.
+    LobbyMemberUpdate{
+        lobby_id: String,
+        member: LobbyMember,
+    },
+    /// A member disconnected from a subscribed lobby.
+    LobbyMemberDisconnect{
+        lobby_id: String,
+        member: LobbyMember,
+    },
+    /// Data was sent to a subscribed lobby.
+    LobbyMessage{
+        lobby_id: String,
+        sender_id: String,
+        data: json::Value,
+    },
+    /// The user pressed keys during a shortcut capture.
+    CaptureShortcutChange(Vec<ShortcutKey>),
+    /// A user joined a subscribed voice channel.
+    VoiceStateCreate(VoiceState),
+    /// The voice state of a user changed in a subscribed voice channel.
+    VoiceStateUpdate(VoiceState),
+    /// A user left a subscribed voice channel.
+    VoiceStateDelete(VoiceState),
+    /// A user started speaking in a subscribed voice channel.
+    SpeakingStart{
+        user_id: String,
+        channel_id: Option<String>,
+    },
+    /// A user stopped speaking in a subscribed voice channel.
+    SpeakingStop{
+        user_id: String,
+        channel_id: Option<String>,
+    },
+    /// A message was sent in a subscribed text channel.
+    MessageCreate{
+        channel_id: String,
+        message: ChatMessage,
+    },
+    /// A message was edited in a subscribed text channel.
+    MessageUpdate{
+        channel_id: String,
+        message: ChatMessage,
+    },
+    /// A message was deleted in a subscribed text channel.
+    MessageDelete{
+        channel_id: String,
+        message_id: String,
+    },
+    /// The Discord client showed a notification.
+    NotificationCreate(Notification),
+    /// The voice connection of the user changed.
+    VoiceConnectionStatus(VoiceConnectionStatus),
+    /// The voice settings of the user changed.
+    VoiceSettingsUpdate(VoiceSettings),
+    /// An event that has no typed representation in this crate.
+    Other{
+        evt: String,
+        data: json::Value,
+    },
+}
+
+impl Event {
+    /// Creates an `Event` from a `Message` sent by the server. Returns `None`,
+    /// if the `Message` is not an event.
+    pub(crate) fn from_message(message: &Message) -> Option<Self> {
+        let evt = message.value("evt")?;
+        if evt == "ERROR" {
+            return Some(Self::Error(Error::Rpc(RpcError::from_message(message))));
+        }
+        let data = &message.payload()["data"];
+        let event = match evt {
+            "GUILD_STATUS" => Guild::from_json(&data["guild"]).map(|guild| Self::GuildStatus{
+                guild,
+                online: data["online"].as_u64().unwrap_or_default() as u32,
+            }),
+            "GUILD_CREATE" => Guild::from_json(data).map(Self::GuildCreate),
+            "CHANNEL_CREATE" => Channel::from_json(data).map(Self::ChannelCreate),
+            "CURRENT_USER_UPDATE" => User::from_json(data).map(Self::CurrentUserUpdate),
+            "ACTIVITY_JOIN" => data["secret"].as_str().map(|secret| Self::ActivityJoin{
+                secret: secret.to_string(),
+            }),
+            "ACTIVITY_SPECTATE" => data["secret"].as_str().map(|secret| Self::ActivitySpectate{
+                secret: secret.to_string(),
+            }),
+            "ACTIVITY_JOIN_REQUEST" => User::from_json(&data["user"]).map(Self::ActivityJoinRequest),
+            "ACTIVITY_INVITE" => ActivityInvite::from_json(data).map(Self::ActivityInvite),
+            "OVERLAY" => Some(Self::Overlay(OverlayState::from_json(data))),
+            "OVERLAY_UPDATE" => Some(Self::OverlayUpdate(OverlayState::from_json(data))),
+            "LOBBY_UPDATE" => Lobby::from_json(data).map(Self::LobbyUpdate),
+            "LOBBY_DELETE" => data["id"].as_str().map(|lobby_id| Self::LobbyDelete{
+                lobby_id: lobby_id.to_string(),
+                reason: data["reason"].as_i64().unwrap_or_default() as i32,
+            }),
+            "LOBBY_MEMBER_CONNECT" => LobbyMember::from_event(data)
+                .map(|(lobby_id, member)| Self::LobbyMemberConnect{ lobby_id, member }),
+            "LOBBY_MEMBER_UPDATE" => LobbyMember::from_event(data)
+                .map(|(lobby_id, member)| Self::LobbyMemberUpdate{ lobby_id, member }),
+            "LOBBY_MEMBER_DISCONNECT" => LobbyMember::from_event(data)
+                .map(|(lobby_id, member)| Self::LobbyMemberDisconnect{ lobby_id, member }),
+            "LOBBY_MESSAGE" => data["lobby_id"].as_str().map(|lobby_id| Self::LobbyMessage{
+                lobby_id: lobby_id.to_string(),
+                sender_id: data["sender_id"].as_str().unwrap_or_default().to_string(),
+                data: data["data"].clone(),
+            }),
+            "CAPTURE_SHORTCUT_CHANGE" => data["shortcut"].as_array()
+                .map(|s| Self::CaptureShortcutChange(s.iter().map(ShortcutKey::from_json).collect())),
+            "VOICE_STATE_CREATE" => VoiceState::from_json(data).map(Self::VoiceStateCreate),
+            "VOICE_STATE_UPDATE" => VoiceState::from_json(data).map(Self::VoiceStateUpdate),
+            "VOICE_STATE_DELETE" => VoiceState::from_json(data).map(Self::VoiceStateDelete),
+            "SPEAKING_START" => data["user_id"].as_str().map(|user_id| Self::SpeakingStart{
+                user_id: user_id.to_string(),
+                channel_id: data["channel_id"].as_str().map(str::to_string),
+            }),
+            "SPEAKING_STOP" => data["user_id"].as_str().map(|user_id| Self::SpeakingStop{
+                user_id: user_id.to_string(),
+                channel_id: data["channel_id"].as_str().map(str::to_string),
+            }),
+            "MESSAGE_CREATE" => ChatMessage::from_json(&data["message"]).map(|message| Self::MessageCreate{
+                channel_id: data["channel_id"].as_str().unwrap_or_default().to_string(),
+                message,
+            }),
+            "MESSAGE_UPDATE" => ChatMessage::from_json(&data["message"]).map(|message| Self::MessageUpdate{
+                channel_id: data["channel_id"].as_str().unwrap_or_default().to_string(),
+                message,
+            }),
+            "MESSAGE_DELETE" => data["message"]["id"].as_str().map(|message_id| Self::MessageDelete{
+                channel_id: data["channel_id"].as_str().unwrap_or_default().to_string(),
+                message_id: message_id.to_string(),
+            }),
+            "NOTIFICATION_CREATE" => Some(Self::NotificationCreate(Notification::from_json(data))),
+            "VOICE_CONNECTION_STATUS" => Some(Self::VoiceConnectionStatus(VoiceConnectionStatus::from_json(data))),
+            "VOICE_SETTINGS_UPDATE" => Some(Self::VoiceSettingsUpdate(VoiceSettings::from_json(data))),
+            _ => None,
+        };
+        // Anything we couldn't make sense of is still passed on
+        Some(event.unwrap_or_else(|| Self::Other{
+            evt: evt.to_string(),
+            data: data.clone(),
+        }))
+    }
+}
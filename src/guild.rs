@@ -0,0 +1,223 @@
+//! Querying and selecting the guilds and channels of the authenticated user.
+
+use std::time::Duration;
+use serde_json as json;
+use crate::{DiscordRpcHandle, Message, Response, User, VoiceState, Error, RpcErrorCode};
+
+/// A Discord guild (server).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Guild {
+    /// The ID of the guild.
+    pub id: String,
+    /// The name of the guild.
+    pub name: String,
+    /// The URL of the icon of the guild, if it has one.
+    pub icon_url: Option<String>,
+    /// The online members of the guild. Only filled by `get_guild`.
+    pub members: Vec<User>,
+}
+
+impl Guild {
+    /// Creates a `Guild` from its JSON representation. Returns `None`, if the
+    /// guild has no ID.
+    pub(crate) fn from_json(json: &json::Value) -> Option<Self> {
+        Some(Self{
+            id: json["id"].as_str()?.to_string(),
+            name: json["name"].as_str().unwrap_or_default().to_string(),
+            icon_url: json["icon_url"].as_str().map(str::to_string),
+            members: json["members"].as_array()
+                .map(|m| m.iter().filter_map(|m| User::from_json(&m["user"])).collect())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// The different types of channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelType {
+    /// A text channel in a guild.
+    GuildText,
+    /// A direct message.
+    Dm,
+    /// A voice channel in a guild.
+    GuildVoice,
+    /// A direct message between multiple users.
+    GroupDm,
+    /// A category of channels in a guild.
+    GuildCategory,
+    /// An announcement channel in a guild.
+    GuildAnnouncement,
+    /// A store channel in a guild.
+    GuildStore,
+    /// Any other type we don't know about.
+    Unknown(i32),
+}
+
+impl From<i32> for ChannelType {
+    fn from(n: i32) -> Self {
+        match n {
+            0 => Self::GuildText,
+            1 => Self::Dm,
+            2 => Self::GuildVoice,
+            3 => Self::GroupDm,
+            4 => Self::GuildCategory,
+            5 => Self::GuildAnnouncement,
+            6 => Self::GuildStore,
+            x => Self::Unknown(x),
+        }
+    }
+}
+
+/// A Discord channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    /// The ID of the channel.
+    pub id: String,
+    /// The name of the channel.
+    pub name: String,
+    /// The type of the channel.
+    pub ty: ChannelType,
+    /// The ID of the guild the channel is in, if it's in one.
+    pub guild_id: Option<String>,
+    /// The topic of the channel, if set.
+    pub topic: Option<String>,
+    /// The bitrate of the voice channel.
+    pub bitrate: Option<u32>,
+    /// The maximum number of users in the voice channel, `0` for no limit.
+    pub user_limit: Option<u32>,
+    /// The position of the channel in the guild.
+    pub position: Option<i32>,
+    /// The users in the voice channel. Only filled by `get_channel` and
+    /// `get_selected_voice_channel`.
+    pub voice_states: Vec<VoiceState>,
+}
+
+impl Channel {
+    /// Creates a `Channel` from its JSON representation. Returns `None`, if
+    /// the channel has no ID.
+    pub(crate) fn from_json(json: &json::Value) -> Option<Self> {
+        Some(Self{
+            id: json["id"].as_str()?.to_string(),
+            name: json["name"].as_str().unwrap_or_default().to_string(),
+            ty: ChannelType::from(json["type"].as_i64().unwrap_or(-1) as i32),
+            guild_id: json["guild_id"].as_str().map(str::to_string),
+            topic: json["topic"].as_str().map(str::to_string),
+            bitrate: json["bitrate"].as_u64().map(|n| n as u32),
+            user_limit: json["user_limit"].as_u64().map(|n| n as u32),
+            position: json["position"].as_i64().map(|n| n as i32),
+            voice_states: json["voice_states"].as_array()
+                .map(|v| v.iter().filter_map(VoiceState::from_json).collect())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// The error for when the server answered with something unexpected.
+fn invalid(what: &str) -> Error {
+    Error::InvalidMessage(format!("Invalid {} in the response", what))
+}
+
+/// Converts the answer to a channel selection, mapping the selection specific
+/// errors.
+fn selected_channel(data: Result<json::Value, Error>) -> Result<Option<Channel>, Error> {
+    match data {
+        Ok(data) => Ok(Channel::from_json(&data)),
+        Err(Error::Rpc(err)) if err.code == RpcErrorCode::SelectChannelTimedOut =>
+            Err(Error::SelectChannelTimedOut),
+        Err(Error::Rpc(err)) if err.code == RpcErrorCode::SelectVoiceForceRequired =>
+            Err(Error::SelectVoiceForceRequired),
+        Err(err) => Err(err),
+    }
+}
+
+/// Creates the arguments of a channel selection command.
+fn selection_args(channel_id: Option<&str>, timeout: Option<Duration>) -> json::Value {
+    let mut args = json::json!{{ "channel_id": channel_id }};
+    if let Some(timeout) = timeout {
+        // Discord takes whole seconds, round up so short timeouts don't become 0
+        let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        args["timeout"] = secs.into();
+    }
+    args
+}
+
+impl DiscordRpcHandle {
+    /// Lists the guilds the user is in. Needs an authenticated connection.
+    pub fn get_guilds(&self) -> Result<Response<Vec<Guild>>, Error> {
+        let response = self.shared.send(Message::command("GET_GUILDS", json::json!{{}}))?;
+        Ok(response.map(|data| {
+            let data = data?;
+            let guilds = data["guilds"].as_array().ok_or_else(|| invalid("guilds"))?;
+            Ok(guilds.iter().filter_map(Guild::from_json).collect())
+        }))
+    }
+
+    /// Gets the guild with the given ID, including its online members.
+    pub fn get_guild(&self, guild_id: &str) -> Result<Response<Guild>, Error> {
+        let args = json::json!{{ "guild_id": guild_id }};
+        let response = self.shared.send(Message::command("GET_GUILD", args))?;
+        Ok(response.map(|data| Guild::from_json(&data?).ok_or_else(|| invalid("guild"))))
+    }
+
+    /// Lists the channels of the guild with the given ID.
+    pub fn get_channels(&self, guild_id: &str) -> Result<Response<Vec<Channel>>, Error> {
+        let args = json::json!{{ "guild_id": guild_id }};
+        let response = self.shared.send(Message::command("GET_CHANNELS", args))?;
+        Ok(response.map(|data| {
+            let data = data?;
+            let channels = data["channels"].as_array().ok_or_else(|| invalid("channels"))?;
+            Ok(channels.iter().filter_map(Channel::from_json).collect())
+        }))
+    }
+
+    /// Gets the channel with the given ID, including the voice states of the
+    /// users in it.
+    pub fn get_channel(&self, channel_id: &str) -> Result<Response<Channel>, Error> {
+        let args = json::json!{{ "channel_id": channel_id }};
+        let response = self.shared.send(Message::command("GET_CHANNEL", args))?;
+        Ok(response.map(|data| Channel::from_json(&data?).ok_or_else(|| invalid("channel"))))
+    }
+
+    /// Gets the voice channel the user is in, or `None` if they aren't in
+    /// one.
+    pub fn get_selected_voice_channel(&self) -> Result<Response<Option<Channel>>, Error> {
+        let message = Message::command("GET_SELECTED_VOICE_CHANNEL", json::json!{{}});
+        let response = self.shared.send(message)?;
+        Ok(response.map(|data| Ok(Channel::from_json(&data?))))
+    }
+
+    /// Moves the user to the voice channel with the given ID, or out of voice
+    /// with `None`. If the user is already in a voice channel, `force` is
+    /// needed, otherwise the `Response` receives
+    /// `Error::SelectVoiceForceRequired`. With `navigate` the client shows the
+    /// channel too. Discord gives up after `timeout` with
+    /// `Error::SelectChannelTimedOut`, so waiting on the `Response` should
+    /// take longer than that. The `Response` receives the selected channel.
+    pub fn select_voice_channel(
+        &self,
+        channel_id: Option<&str>,
+        timeout: Option<Duration>,
+        force: bool,
+        navigate: bool,
+    ) -> Result<Response<Option<Channel>>, Error> {
+        let mut args = selection_args(channel_id, timeout);
+        args["force"] = force.into();
+        args["navigate"] = navigate.into();
+        let response = self.shared.send(Message::command("SELECT_VOICE_CHANNEL", args))?;
+        Ok(response.map(selected_channel))
+    }
+
+    /// Shows the text channel with the given ID in the client, or closes the
+    /// current one with `None`. Discord gives up after `timeout` with
+    /// `Error::SelectChannelTimedOut`. The `Response` receives the selected
+    /// channel.
+    pub fn select_text_channel(
+        &self,
+        channel_id: Option<&str>,
+        timeout: Option<Duration>,
+    ) -> Result<Response<Option<Channel>>, Error> {
+        let args = selection_args(channel_id, timeout);
+        let response = self.shared.send(Message::command("SELECT_TEXT_CHANNEL", args))?;
+        Ok(response.map(selected_channel))
+    }
+}
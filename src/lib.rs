@@ -1,11 +1,21 @@
-//! TODO: Introduce
+//! A client for the local Discord RPC server, for setting the rich presence
+//! of a game and using the rest of the RPC commands.
+//!
+//! # Features
+//!
+//! - `tokio`: the asynchronous `AsyncDiscordRpc` client.
+//! - `http`: the `HttpTokenExchange`, exchanging OAuth2 codes for tokens over
+//!   HTTP. Without it a `TokenExchange` has to be implemented for `login`.
 
+use std::fmt;
 use std::sync;
 use sync::atomic::{AtomicBool, Ordering};
 use sync::{Arc, Mutex, Condvar};
 use std::thread;
-use std::time::{SystemTime, Duration};
-use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::time::{SystemTime, Duration, Instant};
+use std::ops::Deref;
+use serde_json as json;
 
 mod error;
 pub use error::*;
@@ -14,32 +24,90 @@ pub mod connection;
 use connection::*;
 
 mod message;
-use message::*;
+pub use message::*;
 
 mod windows;
+mod unix;
 
 mod client;
-use client::*;
+pub use client::*;
+
+mod user;
+pub use user::*;
+
+mod response;
+pub use response::Response;
+use response::PendingRequests;
+
+mod event;
+pub use event::*;
+
+mod voice;
+pub use voice::*;
+
+mod guild;
+pub use guild::*;
+
+mod activity;
+pub use activity::*;
+
+mod overlay;
+pub use overlay::*;
+
+mod lobby;
+pub use lobby::*;
+
+mod shortcut;
+pub use shortcut::*;
+
+mod devices;
+pub use devices::*;
+
+mod chat;
+pub use chat::*;
+
+mod subscription;
+pub use subscription::*;
+
+mod auth;
+pub use auth::*;
+
+mod store;
+pub use store::*;
+
+mod queue;
+pub use queue::OverflowPolicy;
+use queue::SendQueue;
+
+mod async_rpc;
+#[cfg(feature = "tokio")]
+pub use async_rpc::*;
 
 // TODO: Store presence so at reconnect we can re-queue it?
 
-/// The Discord RPC client to communicate with the local Discord server.
+/// The Discord RPC client to communicate with the local Discord server. It
+/// dereferences to a `DiscordRpcHandle`, so everything the handles can do is
+/// available here too.
 #[derive(Debug)]
 pub struct DiscordRPC {
-    io_proc: IoProcess,
+    handle: DiscordRpcHandle,
 }
 
 impl DiscordRPC {
     /// Creates a new `DiscordRPC` client with the given `Client`.
     fn with_client(client: Client) -> Self {
+        let io_proc = IoProcess::new(client);
         Self{
-            io_proc: IoProcess::new(client),
+            handle: DiscordRpcHandle{
+                shared: io_proc.shared.clone(),
+                io_proc: Arc::new(Mutex::new(io_proc)),
+            },
         }
     }
 
     /// Creates a new `DiscordRPC` client with the given `Connection` and
     /// application ID.
-    pub fn with_connection<C: Connection>(conn: C, app_id: &str) -> Self where C: 'static {
+    pub fn with_connection<C: Connection + 'static>(conn: C, app_id: &str) -> Self {
         Self::with_client(Client::with_connection(conn, app_id))
     }
 
@@ -49,14 +117,183 @@ impl DiscordRPC {
         Self::with_client(Client::new(app_id))
     }
 
-    /// Starts the client to send and receive messages.
-    pub fn start(&mut self) {
-        self.io_proc.start();
+    /// Returns a new `DiscordRpcHandle` to this client.
+    pub fn handle(&self) -> DiscordRpcHandle {
+        self.handle.clone()
+    }
+
+    /// Starts the client to send and receive messages on a background thread.
+    /// Fails with `Error::ShuttingDown`, if the IO thread abandoned by a
+    /// timed out shutdown is still finishing.
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.handle.io_proc.lock().unwrap().start()
+    }
+
+    /// Does the IO on the calling thread instead of a background one, so this
+    /// should be called periodically (like once per frame) instead of `start`.
+    /// Reconnects, reads and writes messages and calls the handlers on the
+    /// calling thread, spending roughly at most `budget` time doing so. Does
+    /// nothing, if the client was started with `start`.
+    pub fn poll(&mut self, budget: Duration) {
+        self.handle.io_proc.lock().unwrap().poll(budget);
     }
 
-    /// Sets the `RichPresence` for the Discord server.
-    pub fn set_rich_presence(&mut self, rp: Option<RichPresence>) {
-        self.io_proc.send(Message::rich_presence(rp));
+    /// Sets, whether the presence should be cleared when shutting down.
+    /// Defaults to `true`.
+    pub fn set_clear_on_shutdown(&mut self, clear: bool) {
+        self.handle.io_proc.lock().unwrap().clear_on_shutdown = clear;
+    }
+
+    /// Sets the timeout for the shutdown that happens when the last handle to
+    /// the client is dropped. Defaults to 1 second.
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) {
+        self.handle.io_proc.lock().unwrap().shutdown_timeout = timeout;
+    }
+
+    /// Sets the `KeepAlive` settings for detecting dead connections, `None`
+    /// disables it. Disabled by default.
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepAlive>) {
+        *self.handle.shared.keepalive.lock().unwrap() = keepalive;
+        self.handle.shared.notify();
+    }
+
+    /// Sets the `TokenStore` the OAuth2 tokens are persisted in.
+    pub fn set_token_store<S>(&mut self, store: S) where S: TokenStore + 'static {
+        self.handle.shared.auth.lock().unwrap().store = Some(Arc::new(store));
+    }
+
+    /// Sets the maximum number of messages waiting to be sent. Defaults to 256.
+    pub fn set_queue_capacity(&mut self, capacity: usize) {
+        self.handle.shared.send_queue.lock().unwrap().set_capacity(capacity);
+    }
+
+    /// Sets what happens when a message is sent while the send queue is full.
+    /// Defaults to `OverflowPolicy::DropOldest`.
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.handle.shared.send_queue.lock().unwrap().set_policy(policy);
+    }
+
+    /// Sets the handler that is called when the connection to the Discord
+    /// server is ready for commands.
+    pub fn on_connect<F>(&mut self, f: F) where F: Fn() + Send + Sync + 'static {
+        self.handle.shared.handlers.lock().unwrap().on_connect = Arc::new(f);
+    }
+
+    /// Sets the handler that is called on errors, including the ones the
+    /// server reports for rejected commands.
+    pub fn on_error<F>(&mut self, f: F) where F: Fn(Error) + Send + Sync + 'static {
+        self.handle.shared.handlers.lock().unwrap().on_error = Arc::new(f);
+    }
+
+    /// Sets the handler that is called when the connection to the Discord
+    /// server is lost.
+    pub fn on_disconnect<F>(&mut self, f: F) where F: Fn() + Send + Sync + 'static {
+        self.handle.shared.handlers.lock().unwrap().on_disconnect = Arc::new(f);
+    }
+
+    /// Sets the handler that is called for events dispatched by the Discord
+    /// server.
+    pub fn on_event<F>(&mut self, f: F) where F: Fn(Event) + Send + Sync + 'static {
+        self.handle.shared.handlers.lock().unwrap().on_event = Arc::new(f);
+    }
+}
+
+impl Deref for DiscordRPC {
+    type Target = DiscordRpcHandle;
+
+    fn deref(&self) -> &DiscordRpcHandle {
+        &self.handle
+    }
+}
+
+/// A cheaply cloneable handle to a `DiscordRPC` client that can be shared
+/// between threads. The client is shut down, when the last handle (including
+/// the `DiscordRPC` itself) is dropped.
+#[derive(Debug, Clone)]
+pub struct DiscordRpcHandle {
+    io_proc: Arc<Mutex<IoProcess>>,
+    shared: Arc<Shared>,
+}
+
+impl DiscordRpcHandle {
+    /// Sets the `RichPresence` for the Discord server. The returned
+    /// `Response` can be used to wait for the server to accept it.
+    pub fn set_rich_presence(&self, rp: Option<RichPresence>) -> Result<Response, Error> {
+        self.shared.send(Message::rich_presence(rp))
+    }
+
+    /// Sends an arbitrary command with the given arguments to the Discord
+    /// server, for the commands this crate doesn't wrap yet. The returned
+    /// `Response` receives the `data` of the answer.
+    pub fn send_raw(&self, cmd: &str, args: json::Value) -> Result<Response, Error> {
+        self.shared.send(Message::command(cmd, args))
+    }
+
+    /// Shuts the client down gracefully. If configured, the presence is
+    /// cleared first, then the pending messages are flushed and the connection
+    /// is closed properly. Waits at most `timeout` for all this to happen, the
+    /// rest is abandoned after that and `Error::Timeout` is returned. If the
+    /// client wasn't connected, `Error::NotConnected` is returned. Either way
+    /// the commands left unanswered receive `Error::ShuttingDown`. The client
+    /// can be started again, once the abandoned IO thread finished. Does
+    /// nothing, if the client was already shut down since the last start.
+    /// Must not be called from a handler while polling.
+    pub fn shutdown(&self, timeout: Duration) -> Result<(), Error> {
+        self.io_proc.lock().unwrap().shutdown(timeout)
+    }
+
+    /// Returns the number of messages waiting in the send queue.
+    pub fn queue_depth(&self) -> usize {
+        self.shared.send_queue.lock().unwrap().len()
+    }
+
+    /// Returns the round-trip time measured by the last keepalive Ping. In
+    /// polling mode it's only as precise as often `poll` is called.
+    pub fn latency(&self) -> Option<Duration> {
+        self.shared.status.lock().unwrap().latency
+    }
+
+    /// Returns `true`, if the client is connected and ready for commands.
+    pub fn is_connected(&self) -> bool {
+        self.shared.status.lock().unwrap().state == ConnectionState::Ready
+    }
+
+    /// Returns the current `ConnectionState` without blocking.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.shared.status.lock().unwrap().state
+    }
+
+    /// Returns the current `ConnectionStatus` without blocking, with the last
+    /// error and the `ReadyInfo` along with the `ConnectionState`.
+    pub fn connection_status(&self) -> ConnectionStatus {
+        self.shared.status.lock().unwrap().clone()
+    }
+
+    /// Blocks until the Discord server is ready to receive commands, or the
+    /// timeout elapses. Returns early with the error, if the server closed the
+    /// connection with a code that makes reconnecting pointless. In polling
+    /// mode this must not be called on the polling thread.
+    pub fn wait_until_ready(&self, timeout: Duration) -> Result<ReadyInfo, Error> {
+        let deadline = Instant::now() + timeout;
+        let mut status = self.shared.status.lock().unwrap();
+        loop {
+            if status.state == ConnectionState::Ready {
+                if let Some(ready_info) = &status.ready_info {
+                    return Ok(ready_info.clone());
+                }
+            }
+            if let Some(err @ Error::ConnectionClosed{ code, .. }) = &status.last_error {
+                if !code.should_reconnect() {
+                    return Err(err.clone());
+                }
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::Timeout);
+            }
+            status = self.shared.status_cv.wait_timeout(status, deadline - now).unwrap().0;
+        }
     }
 }
 
@@ -78,99 +315,520 @@ pub struct RichPresence {
     pub join_secret: String,
     pub spectate_secret: String,
     pub instance: bool,
+    /// Additional fields merged into the activity as-is, overriding the ones
+    /// above. Useful for fields this crate doesn't know about yet.
+    pub extra: json::Map<String, json::Value>,
+}
+
+/// The state of the connection to the Discord server.
+#[derive(Debug, Clone)]
+pub struct ConnectionStatus {
+    /// The `ConnectionState` of the connection.
+    pub state: ConnectionState,
+    /// The last error that occurred on the connection, if any.
+    pub last_error: Option<Error>,
+    /// The information the server sent at the last successful handshake.
+    pub ready_info: Option<ReadyInfo>,
+    /// The round-trip time measured by the last keepalive Ping.
+    pub latency: Option<Duration>,
+}
+
+/// The handlers the user registered for `DiscordRPC` events.
+struct Handlers {
+    on_connect: Arc<dyn Fn() + Send + Sync>,
+    on_error: Arc<dyn Fn(Error) + Send + Sync>,
+    on_disconnect: Arc<dyn Fn() + Send + Sync>,
+    on_event: Arc<dyn Fn(Event) + Send + Sync>,
+}
+
+impl Default for Handlers {
+    fn default() -> Self {
+        Self{
+            on_connect: Arc::new(|| {}),
+            on_error: Arc::new(|_| {}),
+            on_disconnect: Arc::new(|| {}),
+            on_event: Arc::new(|_| {}),
+        }
+    }
+}
+
+impl fmt::Debug for Handlers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handlers").finish()
+    }
+}
+
+/// The state shared between the `IoProcess`, its IO thread and the handles.
+#[derive(Debug)]
+struct Shared {
+    app_id: String,
+    keep_running: AtomicBool,
+    was_ready: AtomicBool,
+    refreshing: AtomicBool,
+    flush_deadline: Mutex<Option<Instant>>,
+    status: Mutex<ConnectionStatus>,
+    status_cv: Condvar,
+    keepalive: Mutex<Option<KeepAlive>>,
+    wait_for_io_mux: Mutex<()>,
+    wait_for_io_cv: Condvar,
+    send_queue: Mutex<SendQueue>,
+    requests: Mutex<PendingRequests>,
+    handlers: Mutex<Handlers>,
+    auth: Mutex<AuthState>,
+    subscriptions: Mutex<Vec<Subscribed>>,
+    certified_devices: Mutex<Option<Vec<CertifiedDevice>>>,
+}
+
+impl Shared {
+    /// Notifies IO activity.
+    fn notify(&self) {
+        self.wait_for_io_cv.notify_all();
+    }
+
+    // The handlers are cloned out before calling them, so they can replace
+    // the handlers themselves
+
+    /// Calls the connect handler.
+    fn emit_connect(&self) {
+        let handler = self.handlers.lock().unwrap().on_connect.clone();
+        handler();
+    }
+
+    /// Calls the error handler.
+    fn emit_error(&self, err: Error) {
+        let handler = self.handlers.lock().unwrap().on_error.clone();
+        handler(err);
+    }
+
+    /// Calls the disconnect handler.
+    fn emit_disconnect(&self) {
+        let handler = self.handlers.lock().unwrap().on_disconnect.clone();
+        handler();
+    }
+
+    /// Calls the event handler.
+    fn emit_event(&self, event: Event) {
+        let handler = self.handlers.lock().unwrap().on_event.clone();
+        handler(event);
+    }
+
+    /// Sends a `Message` to the Discord RPC server. The returned `Response`
+    /// receives the answer correlated by the nonce of the `Message`. If the
+    /// `Message` or an older one is dropped because the send queue is full,
+    /// its `Response` receives `Error::QueueFull`. With
+    /// `OverflowPolicy::Error` the error is returned right away instead.
+    fn send(&self, message: Message) -> Result<Response, Error> {
+        let nonce = message.value("nonce").unwrap_or_default().to_string();
+        let response = self.requests.lock().unwrap().register(&nonce);
+        let pushed = self.send_queue.lock().unwrap().push_back(message);
+        match pushed {
+            Ok(dropped) => {
+                if let Some(nonce) = dropped.as_ref().and_then(|m| m.value("nonce")) {
+                    self.requests.lock().unwrap().resolve(nonce, Err(Error::QueueFull));
+                }
+            },
+            Err(_) => {
+                self.requests.lock().unwrap().remove(&nonce);
+                return Err(Error::QueueFull);
+            },
+        }
+        self.notify();
+        Ok(response)
+    }
+
+    /// Updates the `ConnectionStatus` from the given `Client`.
+    fn update_state(self: &Arc<Self>, client: &Client) {
+        let mut status = self.status.lock().unwrap();
+        status.latency = client.latency();
+        if status.state == client.state() {
+            return;
+        }
+        status.state = client.state();
+        let mut reconnected = false;
+        if status.state == ConnectionState::Ready {
+            status.ready_info = client.ready_info().cloned();
+            status.last_error = None;
+            // Everything sent before the first connection is still queued
+            reconnected = self.was_ready.swap(true, Ordering::SeqCst);
+        }
+        drop(status);
+        if reconnected {
+            self.resume();
+        }
+        self.status_cv.notify_all();
+    }
+
+    /// Updates the cached `User` after it changed.
+    fn update_user(&self, user: &User) {
+        if let Some(ready_info) = &mut self.status.lock().unwrap().ready_info {
+            ready_info.user = Some(user.clone());
+        }
+        if let Some(session) = &mut self.auth.lock().unwrap().session {
+            session.user = Some(user.clone());
+        }
+    }
+
+    /// Restores the state of the previous connection after reconnecting, by
+    /// putting the needed commands in front of the send queue. Commands that
+    /// never made it out are still queued, those aren't duplicated. An expired
+    /// token is refreshed on a helper thread, as the `TokenExchange` blocks,
+    /// the session is resumed once that's done.
+    fn resume(self: &Arc<Self>) {
+        if let Some(devices) = self.certified_devices.lock().unwrap().as_ref() {
+            let message = devices::certified_devices_message(devices);
+            let mut send_queue = self.send_queue.lock().unwrap();
+            // Set while disconnected, the newest list is still waiting
+            let queued = send_queue.iter().any(|m| m.value("cmd") == Some("SET_CERTIFIED_DEVICES"));
+            if !queued {
+                send_queue.push_front(message);
+            }
+        }
+
+        let session = self.auth.lock().unwrap().session.clone();
+        if !session.as_ref().is_some_and(|s| s.token.is_expired()) {
+            self.resume_session(session);
+            return;
+        }
+        if self.refreshing.swap(true, Ordering::SeqCst) {
+            // The helper of a previous reconnect resumes the session
+            return;
+        }
+        let shared = self.clone();
+        thread::spawn(move || {
+            let session = shared.refresh_session();
+            shared.refreshing.store(false, Ordering::SeqCst);
+            shared.resume_session(session);
+            shared.notify();
+        });
+    }
+
+    /// Puts the resubscriptions and the authentication with the given session
+    /// in front of the send queue.
+    fn resume_session(&self, session: Option<AuthSession>) {
+        let mut send_queue = self.send_queue.lock().unwrap();
+
+        // Subscriptions need the authentication, so they go after it
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let mut resubscribed: Vec<EventSubscription> = Vec::new();
+        for subscribed in subscriptions.iter() {
+            if !resubscribed.contains(&subscribed.event) {
+                resubscribed.push(subscribed.event.clone());
+            }
+        }
+        for event in resubscribed.into_iter().rev() {
+            let message = event.subscribe_message();
+            // Subscribed while disconnected, it's still waiting to be sent
+            if send_queue.contains_command(&message) {
+                continue;
+            }
+            // A refusal of the new SUBSCRIBE forgets the subscription too
+            let nonce = message.value("nonce").unwrap_or_default();
+            for subscribed in subscriptions.iter_mut().filter(|s| s.event == event) {
+                subscribed.nonce = nonce.to_string();
+            }
+            send_queue.push_front(message);
+        }
+
+        if let Some(session) = session {
+            let message = Message::authenticate(&session.token.access_token);
+            if !send_queue.contains_command(&message) {
+                send_queue.push_front(message);
+            }
+        }
+    }
+
+    /// Refreshes the expired token of the session and saves it. If that fails
+    /// the session is dropped and the error is reported. Blocks on the
+    /// `TokenExchange`, so it must not be called on the IO thread.
+    fn refresh_session(&self) -> Option<AuthSession> {
+        let (mut session, exchange, store) = {
+            let auth = self.auth.lock().unwrap();
+            (auth.session.clone()?, auth.exchange.clone(), auth.store.clone())
+        };
+
+        let refreshed = match (&exchange, &session.token.refresh_token) {
+            (Some(exchange), Some(refresh_token)) => exchange.refresh(refresh_token),
+            _ => Err(Error::Auth("The session expired and can't be refreshed".into())),
+        };
+        let token = match refreshed {
+            Ok(token) => token,
+            Err(err) => {
+                self.auth.lock().unwrap().session = None;
+                self.emit_error(err);
+                return None;
+            },
+        };
+        if let Some(store) = &store {
+            // The new token is still good for this session
+            if let Err(err) = store.save(&self.app_id, &token) {
+                self.emit_error(err);
+            }
+        }
+        session.token = token;
+        self.auth.lock().unwrap().session = Some(session.clone());
+        Some(session)
+    }
 }
 
 /// The IO thread manager that basically lets us run in a non-blocking way.
 #[derive(Debug)]
 struct IoProcess {
     client: Option<Client>,
-    keep_running: Arc<AtomicBool>,
-    wait_for_io_mux: Arc<Mutex<()>>,
-    wait_for_io_cv: Arc<Condvar>,
-    send_queue: Arc<Mutex<VecDeque<Message>>>,
+    shared: Arc<Shared>,
     thread_handle: Option<thread::JoinHandle<Client>>,
+    thread_done: Option<mpsc::Receiver<Result<(), Error>>>,
+    shut_down: bool,
+    last_connect: SystemTime,
+    clear_on_shutdown: bool,
+    shutdown_timeout: Duration,
 }
 
 impl IoProcess {
     /// Creates a new `IoProcess` with the given `Client`.
-    fn new(client: Client) -> Self {
-        let keep_running = Arc::new(AtomicBool::new(true));
-        let wait_for_io_mux = Arc::new(Mutex::new(()));
-        let wait_for_io_cv = Arc::new(Condvar::new());
-        let send_queue = Arc::new(Mutex::new(VecDeque::new()));
+    fn new(mut client: Client) -> Self {
+        let shared = Arc::new(Shared{
+            app_id: client.app_id().to_string(),
+            keep_running: AtomicBool::new(true),
+            was_ready: AtomicBool::new(false),
+            refreshing: AtomicBool::new(false),
+            flush_deadline: Mutex::new(None),
+            status: Mutex::new(ConnectionStatus{
+                state: ConnectionState::Disconnected,
+                last_error: None,
+                ready_info: None,
+                latency: None,
+            }),
+            status_cv: Condvar::new(),
+            keepalive: Mutex::new(None),
+            wait_for_io_mux: Mutex::new(()),
+            wait_for_io_cv: Condvar::new(),
+            send_queue: Mutex::new(SendQueue::new(256, OverflowPolicy::DropOldest)),
+            requests: Mutex::new(PendingRequests::default()),
+            handlers: Mutex::new(Handlers::default()),
+            auth: Mutex::new(AuthState::default()),
+            subscriptions: Mutex::new(Vec::new()),
+            certified_devices: Mutex::new(None),
+        });
+
+        // Route the client events to the user handlers
+        {
+            let shared = shared.clone();
+            client.set_on_connect(move || {
+                shared.emit_connect();
+            });
+        }
+        {
+            let shared = shared.clone();
+            client.set_on_error(move |err| {
+                if let Error::ConnectionClosed{ code: CloseCode::TokenRevoked, .. } = &err {
+                    shared.auth.lock().unwrap().revoke(&shared.app_id);
+                }
+                shared.status.lock().unwrap().last_error = Some(err.clone());
+                shared.status_cv.notify_all();
+                shared.emit_error(err);
+            });
+        }
+        {
+            let shared = shared.clone();
+            client.set_on_disconnect(move || {
+                // Whatever was written won't be answered anymore
+                shared.requests.lock().unwrap().fail_sent(
+                    &Error::PipeClosed("Disconnected before a response arrived".into()));
+                shared.emit_disconnect();
+            });
+        }
+
         Self{
             client: Some(client),
-            keep_running,
-            wait_for_io_mux,
-            wait_for_io_cv,
-            send_queue,
+            shared,
             thread_handle: None,
+            thread_done: None,
+            shut_down: false,
+            last_connect: SystemTime::UNIX_EPOCH,
+            clear_on_shutdown: true,
+            shutdown_timeout: Duration::from_millis(1000),
         }
     }
 
-    /// Starts the IO thread.
-    fn start(&mut self) {
-        if self.thread_handle.is_some() {
-            return;
+    /// Starts the IO thread. Fails, if a previous shutdown abandoned the IO
+    /// thread and it's still finishing.
+    fn start(&mut self) -> Result<(), Error> {
+        if !self.reclaim() {
+            // Either already started, or still shutting down
+            return if self.shared.keep_running.load(Ordering::Relaxed) {
+                Ok(())
+            }
+            else {
+                Err(Error::ShuttingDown)
+            };
         }
 
-        self.keep_running.store(true, Ordering::Relaxed);
+        // The client is only lost, if the IO thread panicked
+        let mut client = match self.client.take() {
+            Some(client) => client,
+            None => return Err(Error::NotConnected),
+        };
+
+        self.shared.keep_running.store(true, Ordering::Relaxed);
+        *self.shared.flush_deadline.lock().unwrap() = None;
+        self.shut_down = false;
 
-        let mut client = self.client.take().unwrap();
-        let keep_running = self.keep_running.clone();
-        let wait_for_io_mux = self.wait_for_io_mux.clone();
-        let wait_for_io_cv = self.wait_for_io_cv.clone();
-        let send_queue = self.send_queue.clone();
+        let shared = self.shared.clone();
+        let (done_sender, done_receiver) = mpsc::channel();
+        self.thread_done = Some(done_receiver);
 
         self.thread_handle = Some(thread::spawn(move || {
             const MAX_WAIT: Duration = Duration::from_millis(500);
+            // Reading often while a Pong is due keeps the latency precise
+            const PONG_WAIT: Duration = Duration::from_millis(5);
 
             let mut last_connect = SystemTime::UNIX_EPOCH;
-            Self::update_client(&mut client, &mut last_connect, &send_queue);
-            while keep_running.load(Ordering::Relaxed) {
-                let lock = wait_for_io_mux.lock().unwrap();
-                let _ = wait_for_io_cv.wait_timeout(lock, MAX_WAIT);
-                Self::update_client(&mut client, &mut last_connect, &send_queue);
+            Self::update_client(&mut client, &mut last_connect, &shared, None);
+            while shared.keep_running.load(Ordering::Relaxed) {
+                let wait = if client.awaiting_pong() {
+                    PONG_WAIT
+                }
+                else {
+                    client.next_ping()
+                        .map_or(MAX_WAIT, |p| p.saturating_duration_since(Instant::now()).min(MAX_WAIT))
+                };
+                let lock = shared.wait_for_io_mux.lock().unwrap();
+                let _ = shared.wait_for_io_cv.wait_timeout(lock, wait);
+                Self::update_client(&mut client, &mut last_connect, &shared, None);
             }
 
+            let flush_deadline = *shared.flush_deadline.lock().unwrap();
+            let flushed = match flush_deadline {
+                Some(deadline) => Self::flush(&mut client, &shared, deadline),
+                None => Ok(()),
+            };
+            let _ = done_sender.send(flushed);
+
             client
         }));
+        Ok(())
+    }
+
+    /// Does a single round of IO on the calling thread, if there's no IO
+    /// thread running.
+    fn poll(&mut self, budget: Duration) {
+        let deadline = Instant::now() + budget;
+        self.reclaim();
+        if let Some(client) = self.client.as_mut() {
+            Self::update_client(client, &mut self.last_connect, &self.shared, Some(deadline));
+        }
     }
 
-    /// Stops the IO thread.
-    fn stop(&mut self) {
+    /// Stops the IO thread gracefully, clearing the presence if configured and
+    /// flushing the pending messages. Waits at most `timeout`, after that the
+    /// IO thread is abandoned and `Error::Timeout` is returned. The abandoned
+    /// thread is reclaimed, once it finished. Only runs once until the next
+    /// start.
+    fn shutdown(&mut self, timeout: Duration) -> Result<(), Error> {
+        if self.shut_down {
+            return Ok(());
+        }
+        self.shut_down = true;
+        let deadline = Instant::now() + timeout;
+
+        if self.clear_on_shutdown {
+            let _ = self.shared.send(Message::rich_presence(None));
+        }
+
+        self.reclaim();
+        if let Some(client) = self.client.as_mut() {
+            // No IO thread, we can flush right here
+            return Self::flush(client, &self.shared, deadline);
+        }
         if self.thread_handle.is_none() {
-            return;
+            return Ok(());
         }
 
-        self.keep_running.store(false, Ordering::Relaxed);
-        self.notify();
-        self.client = Some(self.thread_handle.take().unwrap().join().unwrap());
+        *self.shared.flush_deadline.lock().unwrap() = Some(deadline);
+        self.shared.keep_running.store(false, Ordering::Relaxed);
+        self.shared.notify();
+
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let flushed = match self.thread_done.as_ref().map(|done| done.recv_timeout(timeout)) {
+            Some(Ok(flushed)) => flushed,
+            // The thread is left to finish on its own, it's reclaimed later
+            _ => return Err(Error::Timeout),
+        };
+        self.thread_done = None;
+        let thread_handle = self.thread_handle.take().unwrap();
+        self.client = thread_handle.join().ok();
+        flushed
     }
 
-    /// Notifies IO activity.
-    fn notify(&mut self) {
-        self.wait_for_io_cv.notify_all();
+    /// Takes the `Client` back from the IO thread, if it finished. Returns
+    /// `false`, if the IO thread is still running.
+    fn reclaim(&mut self) -> bool {
+        match &self.thread_handle {
+            Some(thread_handle) if !thread_handle.is_finished() => false,
+            Some(_) => {
+                self.thread_done = None;
+                self.client = self.thread_handle.take().unwrap().join().ok();
+                true
+            },
+            None => true,
+        }
     }
 
-    /// Sends a `Message` to the Discord RPC server.
-    fn send(&mut self, message: Message) {
-        self.send_queue.lock().unwrap().push_back(message);
-        self.notify();
+    /// Writes out the pending messages, then closes the connection properly.
+    /// A handshake in progress is given until the deadline to finish, so the
+    /// pending messages can still go out. The commands left unanswered are
+    /// failed with `Error::ShuttingDown`. Returns an error, if not everything
+    /// could be written.
+    fn flush(client: &mut Client, shared: &Arc<Shared>, deadline: Instant) -> Result<(), Error> {
+        while matches!(client.state(), ConnectionState::Connecting | ConnectionState::HandshakeSent)
+            && Instant::now() < deadline {
+
+            if client.open().is_err() {
+                break;
+            }
+            if client.state() != ConnectionState::Ready {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let ready = client.state() == ConnectionState::Ready;
+        if ready {
+            Self::write_pending(client, shared, Some(deadline));
+        }
+        // The Close frame is sent whenever the pipe is open
+        client.disconnect();
+        shared.update_state(client);
+
+        // Nothing is going to be sent or answered anymore
+        let unsent = {
+            let mut send_queue = shared.send_queue.lock().unwrap();
+            let unsent = send_queue.len();
+            send_queue.clear();
+            unsent
+        };
+        shared.requests.lock().unwrap().fail_all(&Error::ShuttingDown);
+        match unsent {
+            0 => Ok(()),
+            _ if ready => Err(Error::Timeout),
+            _ => Err(Error::NotConnected),
+        }
     }
 
-    /// Updates the `Client` by doing IO.
-    fn update_client(client: &mut Client, last_connect: &mut SystemTime, send_queue: &Arc<Mutex<VecDeque<Message>>>) {
+    /// Updates the `Client` by doing IO. If there's a deadline given, reading
+    /// and writing stops when it passes, leaving the rest for the next update.
+    fn update_client(
+        client: &mut Client,
+        last_connect: &mut SystemTime,
+        shared: &Arc<Shared>,
+        deadline: Option<Instant>,
+    ) {
+        let out_of_time = || deadline.is_some_and(|d| Instant::now() >= d);
+
+        client.set_keepalive(*shared.keepalive.lock().unwrap());
+
         if !client.is_open() {
-            const RECONNECT_DELAY: Duration = Duration::from_millis(1000);
-
-            // Try reconnecting, if there's a second elapsed since the last try
-            let now = SystemTime::now();
-            if let Ok(elapsed) = now.duration_since(*last_connect) {
-                if elapsed >= RECONNECT_DELAY {
-                    *last_connect = now;
-                    client.open();
-                }
+            if !out_of_time() {
+                Self::reconnect(client, last_connect);
+                shared.update_state(client);
             }
             return;
         }
@@ -178,35 +836,95 @@ impl IoProcess {
         // We are connected
 
         // Try to read as much as we can
-        loop {
-            let message = client.read();
-            if message.is_none() {
-                // Didn't read anything, stop
+        while let Ok(Some(message)) = client.read() {
+            let evt = message.value("evt");
+            let nonce = message.value("nonce");
+
+            if evt == Some("ERROR") {
+                let err = RpcError::from_message(&message);
+                if let Some(nonce) = nonce {
+                    shared.requests.lock().unwrap().resolve(nonce, Err(Error::Rpc(err.clone())));
+                    if message.value("cmd") == Some("SUBSCRIBE") {
+                        // Don't renew a refused subscription after reconnecting
+                        shared.subscriptions.lock().unwrap().retain(|s| s.nonce != nonce);
+                    }
+                }
+                shared.emit_error(Error::Rpc(err));
+            }
+            else if let Some(nonce) = nonce {
+                let data = message.payload()["data"].clone();
+                shared.requests.lock().unwrap().resolve(nonce, Ok(data));
+            }
+            else if let Some(event) = Event::from_message(&message) {
+                if let Event::CurrentUserUpdate(user) = &event {
+                    shared.update_user(user);
+                }
+                shared.emit_event(event);
+            }
+
+            if out_of_time() {
                 break;
             }
+        }
 
-            let message = message.unwrap();
-            let _evt = message.value("evt");
-            let nonce = message.value("nonce");
+        // A dead connection is closed here, we'll reconnect in the next update
+        let _ = client.keepalive();
 
-            if nonce.is_some() {
-                // TODO: If evt == "ERROR", report error
-            }
-            else {
-                // TODO:
-                // - ACTIVITY_JOIN
-                // - ACTIVITY_SPECTATE
-                // - ACTIVITY_JOIN_REQUEST
+        Self::write_pending(client, shared, deadline);
+        shared.update_state(client);
+    }
+
+    /// Advances opening the `Client`. Only starts reconnecting, if enough time
+    /// elapsed since the last try.
+    fn reconnect(client: &mut Client, last_connect: &mut SystemTime) {
+        const RECONNECT_DELAY: Duration = Duration::from_millis(1000);
+        const RATE_LIMITED_DELAY: Duration = Duration::from_millis(10000);
+
+        if client.state() != ConnectionState::Disconnected {
+            // Already in the middle of opening
+            let _ = client.open();
+            return;
+        }
+
+        // Don't bother reconnecting, if the server told us it would reject us
+        // again anyway
+        let close_code = client.close_code();
+        if close_code.is_some_and(|c| !c.should_reconnect()) {
+            return;
+        }
+        let delay = if close_code == Some(CloseCode::RateLimited) {
+            RATE_LIMITED_DELAY
+        }
+        else {
+            RECONNECT_DELAY
+        };
+
+        let now = SystemTime::now();
+        if let Ok(elapsed) = now.duration_since(*last_connect) {
+            if elapsed >= delay {
+                *last_connect = now;
+                let _ = client.open();
             }
         }
+    }
 
-        // Write all pending messages
-        {
-            let mut send_queue = send_queue.lock().unwrap();
-            while let Some(msg) = send_queue.pop_front() {
-                if !client.write(msg) {
-                    // TODO: Retry?
-                }
+    /// Writes the pending messages, until the optional deadline passes. Only
+    /// called when the `Client` is ready, so the commands wait in the queue
+    /// while disconnected or handshaking.
+    fn write_pending(client: &mut Client, shared: &Shared, deadline: Option<Instant>) {
+        let mut send_queue = shared.send_queue.lock().unwrap();
+        while let Some(msg) = send_queue.pop_front() {
+            if client.write(&msg).is_err() {
+                // Put it back to be the first to retry when we can write again
+                send_queue.push_front(msg);
+                break;
+            }
+            if let Some(nonce) = msg.value("nonce") {
+                shared.requests.lock().unwrap().mark_sent(nonce);
+            }
+
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                break;
             }
         }
     }
@@ -214,7 +932,7 @@ impl IoProcess {
 
 impl Drop for IoProcess {
     fn drop(&mut self) {
-        self.stop();
+        let _ = self.shutdown(self.shutdown_timeout);
     }
 }
 
@@ -228,3 +946,380 @@ fn nonce() -> String {
     use uuid::Uuid;
     Uuid::new_v4().to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// The state of a scripted in-memory Discord server.
+    #[derive(Debug, Default)]
+    struct Server {
+        online: bool,
+        answer_pings: bool,
+        refused_events: Vec<&'static str>,
+        open: bool,
+        incoming: VecDeque<u8>,
+        written: Vec<Message>,
+    }
+
+    impl Server {
+        /// Returns the commands the client wrote, in order.
+        fn commands(&self) -> Vec<&str> {
+            self.written.iter().filter_map(|m| m.value("cmd")).collect()
+        }
+
+        /// Returns the commands the client wrote, with the event for the
+        /// subscriptions, like `SUBSCRIBE OVERLAY`.
+        fn commands_with_events(&self) -> Vec<String> {
+            self.written.iter().filter_map(|m| {
+                let cmd = m.value("cmd")?;
+                Some(match m.value("evt") {
+                    Some(evt) => format!("{} {}", cmd, evt),
+                    None => cmd.to_string(),
+                })
+            }).collect()
+        }
+
+        /// Drops the connection.
+        fn disconnect(&mut self) {
+            self.open = false;
+            self.incoming.clear();
+        }
+
+        /// Answers a command of the client.
+        fn answer(&mut self, command: &Message) {
+            let cmd = command.value("cmd");
+            let evt = command.value("evt");
+            let refused = cmd == Some("SUBSCRIBE") && evt.is_some_and(|e| self.refused_events.contains(&e));
+            let answer = if refused {
+                json::json!{{
+                    "cmd": cmd,
+                    "evt": "ERROR",
+                    "nonce": command.value("nonce"),
+                    "data": { "code": 4006, "message": "Not authenticated" },
+                }}
+            }
+            else {
+                json::json!{{
+                    "cmd": cmd,
+                    "evt": evt,
+                    "nonce": command.value("nonce"),
+                    "data": {},
+                }}
+            };
+            self.incoming.extend(Message::new(MessageType::Frame, answer).encode());
+        }
+    }
+
+    /// A `Connection` to a `Server`, that answers the handshake and the Pings,
+    /// if configured, right away.
+    struct MockConnection(Arc<Mutex<Server>>);
+
+    impl Connection for MockConnection {
+        fn open(&mut self) -> bool {
+            let mut server = self.0.lock().unwrap();
+            server.open = server.online;
+            server.open
+        }
+
+        fn is_open(&self) -> bool {
+            self.0.lock().unwrap().open
+        }
+
+        fn close(&mut self) {
+            let mut server = self.0.lock().unwrap();
+            server.open = false;
+            server.incoming.clear();
+        }
+
+        fn read(&mut self, buffer: &mut [u8]) -> bool {
+            let mut server = self.0.lock().unwrap();
+            if server.incoming.len() < buffer.len() {
+                return false;
+            }
+            let len = buffer.len();
+            for (b, x) in buffer.iter_mut().zip(server.incoming.drain(..len)) {
+                *b = x;
+            }
+            true
+        }
+
+        fn write(&mut self, buffer: &[u8]) -> bool {
+            let mut server = self.0.lock().unwrap();
+            if !server.open {
+                return false;
+            }
+            let mut header = [0u8; 8];
+            header.copy_from_slice(&buffer[..8]);
+            let (ty, _) = Message::decode_header(header).unwrap();
+            let message = Message::decode_payload(ty, buffer[8..].to_vec()).unwrap();
+            if ty == MessageType::Handshake {
+                let ready = Message::new(MessageType::Frame, json::json!{{
+                    "cmd": "DISPATCH",
+                    "evt": "READY",
+                    "data": { "v": 1 },
+                }});
+                server.incoming.extend(ready.encode());
+            }
+            if ty == MessageType::Frame && message.value("nonce").is_some() {
+                server.answer(&message);
+            }
+            if ty == MessageType::Ping && server.answer_pings {
+                let mut pong = message.clone();
+                pong.set_ty(MessageType::Pong);
+                server.incoming.extend(pong.encode());
+            }
+            server.written.push(message);
+            true
+        }
+    }
+
+    /// Creates a polled client talking to a new `Server`.
+    fn mock_client(online: bool) -> (DiscordRPC, Arc<Mutex<Server>>) {
+        let server = Arc::new(Mutex::new(Server{ online, ..Default::default() }));
+        let rpc = DiscordRPC::with_connection(MockConnection(server.clone()), "1");
+        (rpc, server)
+    }
+
+    /// Polls the client until it's connected.
+    fn poll_until_connected(rpc: &mut DiscordRPC) {
+        for _ in 0..100 {
+            rpc.poll(Duration::from_millis(10));
+            if rpc.is_connected() {
+                return;
+            }
+        }
+        panic!("The client didn't connect");
+    }
+
+    /// Polls the client until the connection drops, then until it's connected
+    /// again and sent everything queued.
+    fn poll_until_reconnected(rpc: &mut DiscordRPC) {
+        for _ in 0..100 {
+            rpc.poll(Duration::from_millis(10));
+            if !rpc.is_connected() {
+                break;
+            }
+        }
+        // The reconnect is delayed by a second
+        for _ in 0..300 {
+            rpc.poll(Duration::from_millis(10));
+            if rpc.is_connected() && rpc.queue_depth() == 0 {
+                rpc.poll(Duration::from_millis(10));
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("The client didn't reconnect");
+    }
+
+    /// Logs the client in with a token that doesn't expire.
+    fn fake_session(rpc: &DiscordRPC) {
+        rpc.shared.auth.lock().unwrap().session = Some(AuthSession{
+            token: Token{
+                access_token: "token".into(),
+                refresh_token: None,
+                expires_at: None,
+                scopes: Vec::new(),
+            },
+            scopes: Vec::new(),
+            user: None,
+        });
+    }
+
+    #[test]
+    fn shutdown_without_connection_fails_pending() {
+        let (rpc, _server) = mock_client(false);
+        let response = rpc.send_raw("TEST", json::json!{{}}).unwrap();
+        assert!(matches!(rpc.shutdown(Duration::from_millis(50)), Err(Error::NotConnected)));
+        assert_eq!(rpc.queue_depth(), 0);
+        assert!(matches!(response.try_get(), Some(Err(Error::ShuttingDown))));
+    }
+
+    #[test]
+    fn shutdown_clears_presence_once() {
+        let (mut rpc, server) = mock_client(true);
+        poll_until_connected(&mut rpc);
+        rpc.shutdown(Duration::from_millis(500)).unwrap();
+        // Neither a second shutdown nor dropping queues another clear
+        rpc.shutdown(Duration::from_millis(500)).unwrap();
+        assert_eq!(rpc.queue_depth(), 0);
+        drop(rpc);
+
+        let server = server.lock().unwrap();
+        assert_eq!(server.commands(), ["SET_ACTIVITY"]);
+        assert_eq!(server.written.last().unwrap().ty(), MessageType::Close);
+    }
+
+    #[test]
+    fn keepalive_measures_round_trip() {
+        let (mut rpc, server) = mock_client(true);
+        server.lock().unwrap().answer_pings = true;
+        rpc.set_keepalive(Some(KeepAlive{
+            interval: Duration::from_millis(50),
+            timeout: Duration::from_secs(1),
+        }));
+        rpc.start().unwrap();
+        rpc.wait_until_ready(Duration::from_secs(1)).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while rpc.latency().is_none() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        // Not the 500ms the IO thread sleeps at most
+        assert!(rpc.latency().unwrap() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn keepalive_reports_dead_connection() {
+        let (mut rpc, _server) = mock_client(true);
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        rpc.on_error(move |err| {
+            let _ = sender.lock().unwrap().send(err);
+        });
+        rpc.set_keepalive(Some(KeepAlive{
+            interval: Duration::from_millis(20),
+            timeout: Duration::from_millis(50),
+        }));
+        rpc.start().unwrap();
+
+        let err = receiver.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert!(matches!(err, Error::KeepAliveTimeout));
+    }
+
+    #[test]
+    fn first_connect_sends_everything_once() {
+        let (mut rpc, server) = mock_client(true);
+        let _overlay = rpc.subscribe(EventSubscription::Overlay).unwrap();
+        assert_eq!(rpc.connection_state(), ConnectionState::Disconnected);
+        poll_until_connected(&mut rpc);
+        assert_eq!(rpc.connection_state(), ConnectionState::Ready);
+        rpc.poll(Duration::from_millis(10));
+        assert_eq!(server.lock().unwrap().commands_with_events(), ["SUBSCRIBE OVERLAY"]);
+    }
+
+    #[test]
+    fn reconnect_resubscribes_after_authenticating() {
+        let (mut rpc, server) = mock_client(true);
+        server.lock().unwrap().refused_events.push("ACTIVITY_JOIN");
+        poll_until_connected(&mut rpc);
+        fake_session(&rpc);
+
+        let _overlay = rpc.subscribe(EventSubscription::Overlay).unwrap();
+        let _overlay_again = rpc.subscribe(EventSubscription::Overlay).unwrap();
+        let _update = rpc.subscribe(EventSubscription::CurrentUserUpdate).unwrap();
+        let _join = rpc.subscribe(EventSubscription::ActivityJoin).unwrap();
+        for _ in 0..5 {
+            rpc.poll(Duration::from_millis(10));
+        }
+        // The refused subscription is forgotten
+        assert_eq!(rpc.shared.subscriptions.lock().unwrap().len(), 3);
+
+        {
+            let mut server = server.lock().unwrap();
+            server.written.clear();
+            server.disconnect();
+        }
+        poll_until_reconnected(&mut rpc);
+        assert_eq!(server.lock().unwrap().commands_with_events(), [
+            "AUTHENTICATE",
+            "SUBSCRIBE OVERLAY",
+            "SUBSCRIBE CURRENT_USER_UPDATE",
+        ]);
+    }
+
+    #[test]
+    fn reconnect_skips_queued_subscriptions() {
+        let (mut rpc, server) = mock_client(true);
+        poll_until_connected(&mut rpc);
+        {
+            let mut server = server.lock().unwrap();
+            server.written.clear();
+            server.disconnect();
+        }
+        rpc.poll(Duration::from_millis(10));
+        assert!(!rpc.is_connected());
+
+        // Still queued when the connection comes back
+        let _overlay = rpc.subscribe(EventSubscription::Overlay).unwrap();
+        poll_until_reconnected(&mut rpc);
+        assert_eq!(server.lock().unwrap().commands_with_events(), ["SUBSCRIBE OVERLAY"]);
+    }
+
+    /// Creates a `CertifiedDevice` with the given ID.
+    fn device(id: &str) -> CertifiedDevice {
+        let info = DeviceInfo{ name: "Name".into(), url: "https://example.com".into() };
+        CertifiedDevice{
+            ty: DeviceType::AudioOutput,
+            id: id.into(),
+            vendor: info.clone(),
+            model: info,
+            related: Vec::new(),
+            echo_cancellation: None,
+            noise_suppression: None,
+            automatic_gain_control: None,
+            hardware_mute: None,
+        }
+    }
+
+    /// Returns the IDs of the devices in every `SET_CERTIFIED_DEVICES` the
+    /// client wrote.
+    fn sent_devices(server: &Server) -> Vec<Vec<String>> {
+        server.written.iter()
+            .filter(|m| m.value("cmd") == Some("SET_CERTIFIED_DEVICES"))
+            .map(|m| m.payload()["args"]["devices"].as_array().unwrap().iter()
+                .map(|d| d["id"].as_str().unwrap().to_string())
+                .collect())
+            .collect()
+    }
+
+    #[test]
+    fn reconnect_resends_certified_devices() {
+        let (mut rpc, server) = mock_client(true);
+        poll_until_connected(&mut rpc);
+        rpc.set_certified_devices(vec![device("a")]).unwrap();
+        rpc.poll(Duration::from_millis(10));
+        assert_eq!(sent_devices(&server.lock().unwrap()), [["a"]]);
+
+        {
+            let mut server = server.lock().unwrap();
+            server.written.clear();
+            server.disconnect();
+        }
+        poll_until_reconnected(&mut rpc);
+        assert_eq!(sent_devices(&server.lock().unwrap()), [["a"]]);
+    }
+
+    #[test]
+    fn reconnect_skips_queued_certified_devices() {
+        let (mut rpc, server) = mock_client(true);
+        poll_until_connected(&mut rpc);
+        rpc.set_certified_devices(vec![device("a")]).unwrap();
+        rpc.poll(Duration::from_millis(10));
+        {
+            let mut server = server.lock().unwrap();
+            server.written.clear();
+            server.disconnect();
+        }
+        rpc.poll(Duration::from_millis(10));
+        assert!(!rpc.is_connected());
+
+        // The newest list is still queued when the connection comes back
+        rpc.set_certified_devices(vec![device("b")]).unwrap();
+        poll_until_reconnected(&mut rpc);
+        assert_eq!(sent_devices(&server.lock().unwrap()), [["b"]]);
+    }
+
+    #[test]
+    fn shortcut_capture_timeout_stops() {
+        let (rpc, _server) = mock_client(false);
+        assert!(matches!(rpc.capture_shortcut(Duration::from_millis(10)), Err(Error::Timeout)));
+        let send_queue = rpc.shared.send_queue.lock().unwrap();
+        let actions: Vec<&str> = send_queue.iter()
+            .map(|m| m.payload()["args"]["action"].as_str().unwrap())
+            .collect();
+        assert_eq!(actions, ["START", "STOP"]);
+    }
+}
@@ -0,0 +1,454 @@
+//! Lobbies, groups of players connected by Discord.
+
+use std::collections::HashMap;
+use serde_json as json;
+use crate::{DiscordRpcHandle, Message, Response, User, Event, Error};
+
+/// Who can join a lobby.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LobbyType {
+    /// Only users with the secret can join.
+    Private,
+    /// Anyone can find the lobby by searching.
+    Public,
+}
+
+impl LobbyType {
+    /// Returns the number of the lobby type in the RPC protocol.
+    fn as_i32(self) -> i32 {
+        match self {
+            Self::Private => 1,
+            Self::Public => 2,
+        }
+    }
+}
+
+/// A member of a `Lobby`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LobbyMember {
+    /// The user.
+    pub user: User,
+    /// The metadata of the member.
+    pub metadata: HashMap<String, String>,
+}
+
+impl LobbyMember {
+    /// Creates a `LobbyMember` from its JSON representation. Returns `None`, if
+    /// there's no user in it.
+    fn from_json(json: &json::Value) -> Option<Self> {
+        Some(Self{
+            user: User::from_json(&json["user"])?,
+            metadata: metadata_from_json(&json["metadata"]),
+        })
+    }
+
+    /// Creates a `LobbyMember` from the data of a member event, along with
+    /// the ID of the lobby.
+    pub(crate) fn from_event(data: &json::Value) -> Option<(String, Self)> {
+        let lobby_id = data["lobby_id"].as_str()?.to_string();
+        Some((lobby_id, Self::from_json(&data["member"])?))
+    }
+}
+
+/// A lobby. It can be kept up to date by subscribing to its events and
+/// applying them with `Lobby::apply`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lobby {
+    /// The ID of the lobby.
+    pub id: String,
+    /// Who can join the lobby.
+    pub ty: LobbyType,
+    /// The ID of the user owning the lobby.
+    pub owner_id: String,
+    /// The secret to connect to the lobby with.
+    pub secret: String,
+    /// The maximum number of members.
+    pub capacity: u32,
+    /// `true`, if no one can join the lobby.
+    pub locked: bool,
+    /// The metadata of the lobby.
+    pub metadata: HashMap<String, String>,
+    /// The members of the lobby.
+    pub members: Vec<LobbyMember>,
+    /// `true`, once a `LobbyDelete` event was applied. A deleted lobby has no
+    /// members and ignores any later events.
+    pub deleted: bool,
+}
+
+impl Lobby {
+    /// Creates a `Lobby` from its JSON representation. Returns `None`, if the
+    /// lobby has no ID.
+    pub(crate) fn from_json(json: &json::Value) -> Option<Self> {
+        Some(Self{
+            id: json["id"].as_str()?.to_string(),
+            ty: if json["type"].as_i64() == Some(2) { LobbyType::Public } else { LobbyType::Private },
+            owner_id: json["owner_id"].as_str().unwrap_or_default().to_string(),
+            secret: json["secret"].as_str().unwrap_or_default().to_string(),
+            capacity: json["capacity"].as_u64().unwrap_or_default() as u32,
+            locked: json["locked"].as_bool().unwrap_or(false),
+            metadata: metadata_from_json(&json["metadata"]),
+            members: json["members"].as_array()
+                .map(|m| m.iter().filter_map(LobbyMember::from_json).collect())
+                .unwrap_or_default(),
+            deleted: false,
+        })
+    }
+
+    /// Updates the lobby from an `Event` about it. Returns `true`, if the
+    /// `Event` changed the lobby. Events arriving late for a deleted lobby
+    /// are ignored.
+    pub fn apply(&mut self, event: &Event) -> bool {
+        if self.deleted {
+            return false;
+        }
+        match event {
+            Event::LobbyUpdate(lobby) if lobby.id == self.id => {
+                // The update doesn't always list the members
+                let members = std::mem::take(&mut self.members);
+                *self = lobby.clone();
+                if self.members.is_empty() {
+                    self.members = members;
+                }
+                true
+            },
+            Event::LobbyMemberConnect{ lobby_id, member }
+            | Event::LobbyMemberUpdate{ lobby_id, member } if *lobby_id == self.id => {
+                match self.members.iter_mut().find(|m| m.user.id == member.user.id) {
+                    Some(m) => *m = member.clone(),
+                    None => self.members.push(member.clone()),
+                }
+                true
+            },
+            Event::LobbyMemberDisconnect{ lobby_id, member } if *lobby_id == self.id => {
+                let len = self.members.len();
+                self.members.retain(|m| m.user.id != member.user.id);
+                self.members.len() != len
+            },
+            Event::LobbyDelete{ lobby_id, .. } if *lobby_id == self.id => {
+                self.members.clear();
+                self.deleted = true;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+/// A change to a `Lobby` for `update_lobby`. The fields left as `None` keep
+/// their current value in the lobby.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LobbyPatch {
+    /// Who can join the lobby.
+    pub ty: Option<LobbyType>,
+    /// The ID of the new owner of the lobby.
+    pub owner_id: Option<String>,
+    /// The maximum number of members.
+    pub capacity: Option<u32>,
+    /// Locks or unlocks the lobby.
+    pub locked: Option<bool>,
+    /// The metadata to set, replacing the same keys.
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+impl LobbyPatch {
+    /// Adds the fields that are set to the given arguments.
+    fn write_to(&self, args: &mut json::Value) {
+        if let Some(ty) = self.ty {
+            args["type"] = ty.as_i32().into();
+        }
+        if let Some(owner_id) = &self.owner_id {
+            args["owner_id"] = owner_id.clone().into();
+        }
+        if let Some(capacity) = self.capacity {
+            args["capacity"] = capacity.into();
+        }
+        if let Some(locked) = self.locked {
+            args["locked"] = locked.into();
+        }
+        if let Some(metadata) = &self.metadata {
+            args["metadata"] = json::json!(metadata);
+        }
+    }
+}
+
+/// How a lobby search filter compares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LobbyComparison {
+    /// The metadata is less than or equal to the value.
+    LessThanOrEqual,
+    /// The metadata is less than the value.
+    LessThan,
+    /// The metadata equals the value.
+    Equal,
+    /// The metadata is greater than the value.
+    GreaterThan,
+    /// The metadata is greater than or equal to the value.
+    GreaterThanOrEqual,
+    /// The metadata doesn't equal the value.
+    NotEqual,
+}
+
+impl LobbyComparison {
+    /// Returns the number of the comparison in the RPC protocol.
+    fn as_i32(self) -> i32 {
+        match self {
+            Self::LessThanOrEqual => -2,
+            Self::LessThan => -1,
+            Self::Equal => 0,
+            Self::GreaterThan => 1,
+            Self::GreaterThanOrEqual => 2,
+            Self::NotEqual => 3,
+        }
+    }
+}
+
+/// A filter of a lobby search on the metadata of the lobbies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LobbyFilter {
+    /// The metadata key to filter on.
+    pub key: String,
+    /// The value to compare with.
+    pub value: String,
+    /// How the metadata is compared with the value.
+    pub comparison: LobbyComparison,
+    /// `true`, if the values are compared as numbers instead of strings.
+    pub numeric: bool,
+}
+
+/// How far away lobbies are searched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LobbyDistance {
+    /// Only lobbies in the same region.
+    Local,
+    /// Lobbies in the same and the adjacent regions.
+    Default,
+    /// Lobbies even further away.
+    Extended,
+    /// Lobbies anywhere.
+    Global,
+}
+
+impl LobbyDistance {
+    /// Returns the number of the distance in the RPC protocol.
+    fn as_i32(self) -> i32 {
+        match self {
+            Self::Local => 0,
+            Self::Default => 1,
+            Self::Extended => 2,
+            Self::Global => 3,
+        }
+    }
+}
+
+/// A search for public lobbies.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LobbySearch {
+    /// The filters all found lobbies have to match.
+    pub filters: Vec<LobbyFilter>,
+    /// The maximum number of lobbies to find.
+    pub limit: Option<u32>,
+    /// How far away lobbies are searched.
+    pub distance: Option<LobbyDistance>,
+}
+
+impl LobbySearch {
+    /// Returns the JSON representation of the search.
+    fn to_json(&self) -> json::Value {
+        let filters: Vec<json::Value> = self.filters.iter().map(|f| json::json!{{
+            "key": f.key,
+            "value": f.value,
+            "cmp": f.comparison.as_i32(),
+            "cast": if f.numeric { 2 } else { 1 },
+        }}).collect();
+        let mut args = json::json!{{ "filter": filters, "sort": [] }};
+        if let Some(limit) = self.limit {
+            args["limit"] = limit.into();
+        }
+        if let Some(distance) = self.distance {
+            args["distance"] = distance.as_i32().into();
+        }
+        args
+    }
+}
+
+/// Reads the metadata of a lobby or member.
+fn metadata_from_json(json: &json::Value) -> HashMap<String, String> {
+    json.as_object()
+        .map(|m| m.iter().filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string()))).collect())
+        .unwrap_or_default()
+}
+
+/// Converts the answer to a lobby command.
+fn lobby(data: Result<json::Value, Error>) -> Result<Lobby, Error> {
+    Lobby::from_json(&data?).ok_or_else(|| Error::InvalidMessage("Invalid lobby in the response".into()))
+}
+
+impl DiscordRpcHandle {
+    /// Creates a lobby owned by the user. The `Response` receives the created
+    /// `Lobby`.
+    pub fn create_lobby(
+        &self,
+        ty: LobbyType,
+        capacity: u32,
+        locked: bool,
+        metadata: &HashMap<String, String>,
+    ) -> Result<Response<Lobby>, Error> {
+        let args = json::json!{{
+            "type": ty.as_i32(),
+            "capacity": capacity,
+            "locked": locked,
+            "metadata": metadata,
+        }};
+        let response = self.shared.send(Message::command("CREATE_LOBBY", args))?;
+        Ok(response.map(lobby))
+    }
+
+    /// Changes the lobby with the given ID. Only the owner can do this.
+    pub fn update_lobby(&self, lobby_id: &str, patch: &LobbyPatch) -> Result<Response, Error> {
+        let mut args = json::json!{{ "id": lobby_id }};
+        patch.write_to(&mut args);
+        self.shared.send(Message::command("UPDATE_LOBBY", args))
+    }
+
+    /// Deletes the lobby with the given ID. Only the owner can do this.
+    pub fn delete_lobby(&self, lobby_id: &str) -> Result<Response, Error> {
+        let args = json::json!{{ "id": lobby_id }};
+        self.shared.send(Message::command("DELETE_LOBBY", args))
+    }
+
+    /// Connects to the lobby with the given ID and secret. The `Response`
+    /// receives the `Lobby`.
+    pub fn connect_to_lobby(&self, lobby_id: &str, secret: &str) -> Result<Response<Lobby>, Error> {
+        let args = json::json!{{ "id": lobby_id, "secret": secret }};
+        let response = self.shared.send(Message::command("CONNECT_TO_LOBBY", args))?;
+        Ok(response.map(lobby))
+    }
+
+    /// Disconnects from the lobby with the given ID.
+    pub fn disconnect_from_lobby(&self, lobby_id: &str) -> Result<Response, Error> {
+        let args = json::json!{{ "id": lobby_id }};
+        self.shared.send(Message::command("DISCONNECT_FROM_LOBBY", args))
+    }
+
+    /// Sends the data to every member of the lobby with the given ID.
+    pub fn send_to_lobby(&self, lobby_id: &str, data: &str) -> Result<Response, Error> {
+        let args = json::json!{{ "lobby_id": lobby_id, "data": data }};
+        self.shared.send(Message::command("SEND_TO_LOBBY", args))
+    }
+
+    /// Sets the metadata of the member with the given user ID in the lobby.
+    pub fn update_lobby_member(
+        &self,
+        lobby_id: &str,
+        user_id: &str,
+        metadata: &HashMap<String, String>,
+    ) -> Result<Response, Error> {
+        let args = json::json!{{
+            "lobby_id": lobby_id,
+            "user_id": user_id,
+            "metadata": metadata,
+        }};
+        self.shared.send(Message::command("UPDATE_LOBBY_MEMBER", args))
+    }
+
+    /// Searches for public lobbies. The `Response` receives the lobbies found.
+    pub fn search_lobbies(&self, search: &LobbySearch) -> Result<Response<Vec<Lobby>>, Error> {
+        let response = self.shared.send(Message::command("SEARCH_LOBBIES", search.to_json()))?;
+        Ok(response.map(|data| {
+            let data = data?;
+            let lobbies = data.as_array()
+                .ok_or_else(|| Error::InvalidMessage("Invalid lobbies in the response".into()))?;
+            Ok(lobbies.iter().filter_map(Lobby::from_json).collect())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(id: &str) -> LobbyMember {
+        LobbyMember{
+            user: User{ id: id.into(), ..Default::default() },
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn lobby(id: &str, members: &[&str]) -> Lobby {
+        Lobby::from_json(&json::json!{{
+            "id": id,
+            "type": 2,
+            "capacity": 4,
+            "members": members.iter().map(|m| json::json!{{ "user": { "id": m } }}).collect::<Vec<_>>(),
+        }}).unwrap()
+    }
+
+    #[test]
+    fn from_json() {
+        let lobby = lobby("1", &["a", "b"]);
+        assert_eq!(lobby.ty, LobbyType::Public);
+        assert_eq!(lobby.capacity, 4);
+        assert_eq!(lobby.members, [member("a"), member("b")]);
+        assert!(!lobby.deleted);
+    }
+
+    #[test]
+    fn update_keeps_unlisted_members() {
+        let mut current = lobby("1", &["a"]);
+        let mut update = lobby("1", &[]);
+        update.capacity = 8;
+        assert!(current.apply(&Event::LobbyUpdate(update)));
+        assert_eq!(current.capacity, 8);
+        assert_eq!(current.members, [member("a")]);
+    }
+
+    #[test]
+    fn member_events() {
+        let mut current = lobby("1", &["a"]);
+        let connect = Event::LobbyMemberConnect{ lobby_id: "1".into(), member: member("b") };
+        assert!(current.apply(&connect));
+        assert_eq!(current.members, [member("a"), member("b")]);
+
+        let mut updated = member("a");
+        updated.metadata.insert("ready".into(), "1".into());
+        let update = Event::LobbyMemberUpdate{ lobby_id: "1".into(), member: updated.clone() };
+        assert!(current.apply(&update));
+        assert_eq!(current.members, [updated, member("b")]);
+
+        let disconnect = Event::LobbyMemberDisconnect{ lobby_id: "1".into(), member: member("b") };
+        assert!(current.apply(&disconnect));
+        assert!(!current.apply(&disconnect));
+        assert_eq!(current.members.len(), 1);
+    }
+
+    #[test]
+    fn other_lobbies_are_ignored() {
+        let mut current = lobby("1", &["a"]);
+        let connect = Event::LobbyMemberConnect{ lobby_id: "2".into(), member: member("b") };
+        assert!(!current.apply(&connect));
+        assert!(!current.apply(&Event::LobbyDelete{ lobby_id: "2".into(), reason: 0 }));
+        assert_eq!(current, lobby("1", &["a"]));
+    }
+
+    #[test]
+    fn delete_clears_members() {
+        let mut current = lobby("1", &["a", "b"]);
+        let delete = Event::LobbyDelete{ lobby_id: "1".into(), reason: 0 };
+        assert!(current.apply(&delete));
+        assert!(current.deleted);
+        assert!(current.members.is_empty());
+        assert!(!current.apply(&delete));
+    }
+
+    #[test]
+    fn deleted_lobby_ignores_late_events() {
+        let mut current = lobby("1", &["a"]);
+        assert!(current.apply(&Event::LobbyDelete{ lobby_id: "1".into(), reason: 0 }));
+
+        assert!(!current.apply(&Event::LobbyUpdate(lobby("1", &["a", "b"]))));
+        let connect = Event::LobbyMemberConnect{ lobby_id: "1".into(), member: member("b") };
+        assert!(!current.apply(&connect));
+        assert!(current.deleted);
+        assert!(current.members.is_empty());
+    }
+}
@@ -15,14 +15,14 @@ pub enum MessageType {
     Pong,
 }
 
-impl Into<u32> for MessageType {
-    fn into(self) -> u32 {
-        match self {
-            Self::Handshake => 0,
-            Self::Frame => 1,
-            Self::Close => 2,
-            Self::Ping => 3,
-            Self::Pong => 4,
+impl From<MessageType> for u32 {
+    fn from(ty: MessageType) -> Self {
+        match ty {
+            MessageType::Handshake => 0,
+            MessageType::Frame => 1,
+            MessageType::Close => 2,
+            MessageType::Ping => 3,
+            MessageType::Pong => 4,
         }
     }
 }
@@ -56,6 +56,55 @@ impl Message {
         Self{ msg_type, payload }
     }
 
+    /// Creates the handshake `Message` for the given application ID.
+    pub fn handshake(app_id: &str) -> Self {
+        Self::new(MessageType::Handshake, json::json!{{
+            "v": 1,
+            "client_id": app_id,
+        }})
+    }
+
+    /// Creates a command frame `Message` with the given command name and
+    /// arguments, and a freshly generated nonce.
+    pub fn command(cmd: &str, args: json::Value) -> Self {
+        Self::new(MessageType::Frame, json::json!{{
+            "nonce": nonce(),
+            "cmd": cmd,
+            "args": args,
+        }})
+    }
+
+    /// Creates the `AUTHENTICATE` command `Message` with the given OAuth2
+    /// access token.
+    pub fn authenticate(access_token: &str) -> Self {
+        Self::command("AUTHENTICATE", json::json!{{
+            "access_token": access_token,
+        }})
+    }
+
+    /// Creates the `SUBSCRIBE` command `Message` for the given event and
+    /// arguments.
+    pub fn subscribe(evt: &str, args: json::Value) -> Self {
+        let mut message = Self::command("SUBSCRIBE", args);
+        message.payload["evt"] = json::Value::String(evt.to_string());
+        message
+    }
+
+    /// Creates the `UNSUBSCRIBE` command `Message` for the given event and
+    /// arguments.
+    pub fn unsubscribe(evt: &str, args: json::Value) -> Self {
+        let mut message = Self::command("UNSUBSCRIBE", args);
+        message.payload["evt"] = json::Value::String(evt.to_string());
+        message
+    }
+
+    /// Returns `true`, if both `Message`s are the same command with the same
+    /// arguments, not minding the nonce.
+    pub(crate) fn same_command(&self, other: &Message) -> bool {
+        self.msg_type == other.msg_type
+            && ["cmd", "evt", "args"].iter().all(|key| self.payload.get(key) == other.payload.get(key))
+    }
+
     /// Creates a `Message` for setting a `RichPresence`.
     pub fn rich_presence(rp: Option<RichPresence>) -> Self {
         // Helpers
@@ -71,10 +120,6 @@ impl Message {
                 .map(|t| t.as_secs()).ok()
         }
 
-        let mut json = json::json!{{
-            "nonce": nonce(),
-            "cmd": "SET_ACTIVITY",
-        }};
         let mut args = json::json!{{
             "pid": pid(),
         }};
@@ -145,11 +190,15 @@ impl Message {
                 activity["secrets"] = secrets;
             }
 
+            // Anything the user wants on top
+            for (key, value) in rp.extra {
+                activity[key] = value;
+            }
+
             args["activity"] = activity;
         }
 
-        json["args"] = args;
-        Self::new(MessageType::Frame, json)
+        Self::command("SET_ACTIVITY", args)
     }
 
     /// Returns the `MessageType` of this `Message`.
@@ -162,14 +211,18 @@ impl Message {
         self.payload[key].as_str()
     }
 
+    /// Returns the JSON payload of this `Message`.
+    pub fn payload(&self) -> &json::Value {
+        &self.payload
+    }
+
     /// Sets the `MessageType` of this `Message`.
     pub fn set_ty(&mut self, ty: MessageType) {
         self.msg_type = ty;
     }
 
-    /// Tries to encode this `Message` to the given writer. Returns `true` on
-    /// success.
-    pub fn encode_to(&self, conn: &mut dyn Connection) -> bool {
+    /// Encodes this `Message` into a frame of bytes.
+    pub fn encode(&self) -> Vec<u8> {
         let payload = self.payload.to_string();
         let mut buffer = Vec::with_capacity(8 + payload.len());
 
@@ -179,40 +232,52 @@ impl Message {
         buffer.extend_from_slice(&payload_len.to_le_bytes());
         buffer.extend_from_slice(payload.as_bytes());
 
-        conn.write(&buffer)
+        buffer
     }
 
-    /// Tries to decode a `Message` from the given reader.
-    pub fn decode_from(conn: &mut dyn Connection) -> Result<Option<Self>, Error> {
+    /// Tries to encode this `Message` to the given writer. Returns `true` on
+    /// success.
+    pub fn encode_to(&self, conn: &mut dyn Connection) -> bool {
+        conn.write(&self.encode())
+    }
+
+    /// Decodes the 8 byte header of a frame into the `MessageType` and the
+    /// length of the payload following it.
+    pub fn decode_header(header: [u8; 8]) -> Result<(MessageType, usize), Error> {
         let mut ty = [0u8; 4];
         let mut len = [0u8; 4];
+        ty.copy_from_slice(&header[..4]);
+        len.copy_from_slice(&header[4..]);
+
+        let ty: MessageType = u32::from_le_bytes(ty).try_into()?;
+        Ok((ty, u32::from_le_bytes(len) as usize))
+    }
+
+    /// Decodes the payload of a frame with the given `MessageType`.
+    pub fn decode_payload(ty: MessageType, payload: Vec<u8>) -> Result<Self, Error> {
+        let payload = String::from_utf8(payload).map_err(|err| Error::InvalidMessage(
+            format!("Invalid message frame encoding: {}", err)))?;
+        let payload: json::Value = json::from_str(&payload).map_err(|err| Error::InvalidMessage(
+            format!("Invalid message frame json: {}", err)))?;
+        Ok(Message::new(ty, payload))
+    }
+
+    /// Tries to decode a `Message` from the given reader.
+    pub fn decode_from(conn: &mut dyn Connection) -> Result<Option<Self>, Error> {
+        let mut header = [0u8; 8];
 
         // Message type
-        if !conn.read(&mut ty) {
+        if !conn.read(&mut header[..4]) {
             return Ok(None);
         }
-
-        let ty = u32::from_le_bytes(ty);
-        let ty: MessageType = ty.try_into()?;
-        if !conn.read(&mut len) {
+        if !conn.read(&mut header[4..]) {
             return Err(Error::InvalidMessage("Could not read message length!".into()));
         }
-        let len = u32::from_le_bytes(len);
-        let mut payload = vec![0u8; len as usize];
+        let (ty, len) = Self::decode_header(header)?;
+        let mut payload = vec![0u8; len];
         if !conn.read(&mut payload) {
             return Err(Error::InvalidMessage("Partially read message frame!".into()));
         }
-        let payload =  String::from_utf8(payload);
-        if payload.is_err() {
-            return Err(Error::InvalidMessage(format!(
-                "Invalid message frame encoding: {}", payload.unwrap_err())));
-        }
-        let payload: json::Result<json::Value> = json::from_str(&payload.unwrap());
-        if payload.is_err() {
-            return Err(Error::InvalidMessage(format!(
-                "Invalid message frame json: {}", payload.unwrap_err())));
-        }
-        let payload = payload.unwrap();
-        Ok(Some(Message::new(ty, payload)))
+        Self::decode_payload(ty, payload).map(Some)
     }
 }
@@ -0,0 +1,50 @@
+//! Controlling the in-game overlay of Discord.
+
+use serde_json as json;
+use crate::{DiscordRpcHandle, Message, Response, ActivityActionType, Error, pid};
+
+/// The state of the in-game overlay for the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OverlayState {
+    /// `true`, if the overlay is enabled for the game.
+    pub enabled: bool,
+    /// `true`, if the overlay is locked, meaning it doesn't take the input.
+    pub locked: bool,
+}
+
+impl OverlayState {
+    /// Creates an `OverlayState` from its JSON representation.
+    pub(crate) fn from_json(json: &json::Value) -> Self {
+        Self{
+            enabled: json["enabled"].as_bool().unwrap_or(false),
+            locked: json["locked"].as_bool().unwrap_or(false),
+        }
+    }
+}
+
+impl DiscordRpcHandle {
+    /// Opens the overlay to invite friends to join or spectate the current
+    /// activity.
+    pub fn open_overlay_activity_invite(&self, ty: ActivityActionType) -> Result<Response, Error> {
+        let args = json::json!{{ "pid": pid(), "type": ty.as_i32() }};
+        self.shared.send(Message::command("OPEN_OVERLAY_ACTIVITY_INVITE", args))
+    }
+
+    /// Opens the overlay to join the guild with the given invite code.
+    pub fn open_overlay_guild_invite(&self, code: &str) -> Result<Response, Error> {
+        let args = json::json!{{ "pid": pid(), "code": code }};
+        self.shared.send(Message::command("OPEN_OVERLAY_GUILD_INVITE", args))
+    }
+
+    /// Opens the voice settings in the overlay.
+    pub fn open_overlay_voice_settings(&self) -> Result<Response, Error> {
+        let args = json::json!{{ "pid": pid() }};
+        self.shared.send(Message::command("OPEN_OVERLAY_VOICE_SETTINGS", args))
+    }
+
+    /// Locks or unlocks the overlay. A locked overlay doesn't take the input.
+    pub fn set_overlay_locked(&self, locked: bool) -> Result<Response, Error> {
+        let args = json::json!{{ "pid": pid(), "locked": locked }};
+        self.shared.send(Message::command("SET_OVERLAY_LOCKED", args))
+    }
+}
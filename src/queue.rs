@@ -0,0 +1,147 @@
+//! The queue of messages waiting to be sent to the server.
+
+use std::collections::VecDeque;
+use crate::Message;
+
+/// What to do, when a message is sent while the send queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Drop the oldest queued message to make room for the new one.
+    DropOldest,
+    /// Drop the new message.
+    DropNewest,
+    /// Refuse the new message and report an error to the caller.
+    Error,
+}
+
+/// A bounded queue of `Message`s waiting to be sent.
+#[derive(Debug)]
+pub(crate) struct SendQueue {
+    messages: VecDeque<Message>,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+impl SendQueue {
+    /// Creates an empty `SendQueue` with the given capacity and
+    /// `OverflowPolicy`.
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self{
+            messages: VecDeque::new(),
+            capacity,
+            policy,
+        }
+    }
+
+    /// Returns the number of queued messages.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Sets the capacity of the queue. Already queued messages above the
+    /// capacity are kept.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// Sets the `OverflowPolicy` of the queue.
+    pub fn set_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+    }
+
+    /// Queues a `Message` to be sent last. Returns the `Message` that was
+    /// dropped because of the `OverflowPolicy`, if any. If the policy is
+    /// `OverflowPolicy::Error`, the refused `Message` is returned as an error.
+    pub fn push_back(&mut self, message: Message) -> Result<Option<Message>, Message> {
+        if self.messages.len() < self.capacity {
+            self.messages.push_back(message);
+            return Ok(None);
+        }
+        match self.policy {
+            OverflowPolicy::DropOldest => {
+                let dropped = self.messages.pop_front();
+                self.messages.push_back(message);
+                Ok(dropped)
+            },
+            OverflowPolicy::DropNewest => Ok(Some(message)),
+            OverflowPolicy::Error => Err(message),
+        }
+    }
+
+    /// Puts a `Message` back to be sent first, regardless of the capacity.
+    pub fn push_front(&mut self, message: Message) {
+        self.messages.push_front(message);
+    }
+
+    /// Returns an iterator over the queued messages, the next one first.
+    pub fn iter(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter()
+    }
+
+    /// Returns `true`, if a `Message` with the same command is queued.
+    pub fn contains_command(&self, message: &Message) -> bool {
+        self.messages.iter().any(|m| m.same_command(message))
+    }
+
+    /// Drops every queued `Message`.
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+
+    /// Takes the `Message` that should be sent next.
+    pub fn pop_front(&mut self) -> Option<Message> {
+        self.messages.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json as json;
+
+    fn command(n: i32) -> Message {
+        Message::command("TEST", json::json!{{ "n": n }})
+    }
+
+    fn queued(queue: &SendQueue) -> Vec<i64> {
+        queue.iter().map(|m| m.payload()["args"]["n"].as_i64().unwrap()).collect()
+    }
+
+    #[test]
+    fn drop_oldest_makes_room() {
+        let mut queue = SendQueue::new(2, OverflowPolicy::DropOldest);
+        assert!(queue.push_back(command(1)).unwrap().is_none());
+        assert!(queue.push_back(command(2)).unwrap().is_none());
+        let dropped = queue.push_back(command(3)).unwrap().unwrap();
+        assert_eq!(dropped.payload()["args"]["n"], 1);
+        assert_eq!(queued(&queue), [2, 3]);
+    }
+
+    #[test]
+    fn drop_newest_keeps_queue() {
+        let mut queue = SendQueue::new(2, OverflowPolicy::DropNewest);
+        queue.push_back(command(1)).unwrap();
+        queue.push_back(command(2)).unwrap();
+        let dropped = queue.push_back(command(3)).unwrap().unwrap();
+        assert_eq!(dropped.payload()["args"]["n"], 3);
+        assert_eq!(queued(&queue), [1, 2]);
+    }
+
+    #[test]
+    fn error_refuses_message() {
+        let mut queue = SendQueue::new(1, OverflowPolicy::Error);
+        queue.push_back(command(1)).unwrap();
+        let refused = queue.push_back(command(2)).unwrap_err();
+        assert_eq!(refused.payload()["args"]["n"], 2);
+        assert_eq!(queued(&queue), [1]);
+    }
+
+    #[test]
+    fn push_front_ignores_capacity() {
+        let mut queue = SendQueue::new(1, OverflowPolicy::Error);
+        queue.push_back(command(1)).unwrap();
+        queue.push_front(command(0));
+        assert_eq!(queued(&queue), [0, 1]);
+        assert_eq!(queue.pop_front().unwrap().payload()["args"]["n"], 0);
+    }
+}
@@ -0,0 +1,123 @@
+//! Correlating responses of the RPC server with the commands sent.
+
+use std::sync::mpsc;
+use std::time::Duration;
+use std::collections::HashMap;
+use serde_json as json;
+use crate::Error;
+
+/// Converts the raw result of a command to the type a `Response` yields.
+type Convert<T> = fn(Result<json::Value, Error>) -> Result<T, Error>;
+
+/// A handle to the response of a command sent to the Discord RPC server. By
+/// default it yields the raw `data` of the answer, the typed commands convert
+/// it to something nicer.
+#[derive(Debug)]
+pub struct Response<T = json::Value> {
+    nonce: String,
+    receiver: mpsc::Receiver<Result<json::Value, Error>>,
+    convert: Convert<T>,
+}
+
+impl Response {
+    /// Converts the result with the given function, when it arrives.
+    pub(crate) fn map<T>(self, convert: Convert<T>) -> Response<T> {
+        Response{ nonce: self.nonce, receiver: self.receiver, convert }
+    }
+}
+
+impl<T> Response<T> {
+    /// Returns the nonce of the command this `Response` belongs to.
+    pub fn nonce(&self) -> &str {
+        &self.nonce
+    }
+
+    /// Blocks until the response arrives and returns it.
+    pub fn wait(&self) -> Result<T, Error> {
+        (self.convert)(self.receiver.recv().unwrap_or_else(|_| Err(abandoned())))
+    }
+
+    /// Blocks until the response arrives or the timeout elapses.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<T, Error> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(result) => (self.convert)(result),
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(Error::Timeout),
+            Err(mpsc::RecvTimeoutError::Disconnected) => (self.convert)(Err(abandoned())),
+        }
+    }
+
+    /// Returns the response, if it has already arrived.
+    pub fn try_get(&self) -> Option<Result<T, Error>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some((self.convert)(result)),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some((self.convert)(Err(abandoned()))),
+        }
+    }
+}
+
+/// The error for when the command was dropped without a response.
+fn abandoned() -> Error {
+    Error::PipeClosed("The command was abandoned without a response".into())
+}
+
+/// A command that still waits for its response.
+#[derive(Debug)]
+struct Pending {
+    sender: mpsc::Sender<Result<json::Value, Error>>,
+    sent: bool,
+}
+
+/// Bookkeeping of the commands waiting for a response, keyed by nonce.
+#[derive(Debug, Default)]
+pub(crate) struct PendingRequests {
+    pending: HashMap<String, Pending>,
+}
+
+impl PendingRequests {
+    /// Registers a command with the given nonce and returns the `Response`
+    /// handle for it.
+    pub fn register(&mut self, nonce: &str) -> Response {
+        let (sender, receiver) = mpsc::channel();
+        self.pending.insert(nonce.to_string(), Pending{ sender, sent: false });
+        Response{ nonce: nonce.to_string(), receiver, convert: |result| result }
+    }
+
+    /// Forgets the command with the given nonce, without resolving it.
+    pub fn remove(&mut self, nonce: &str) {
+        self.pending.remove(nonce);
+    }
+
+    /// Marks the command with the given nonce as written to the server.
+    pub fn mark_sent(&mut self, nonce: &str) {
+        if let Some(pending) = self.pending.get_mut(nonce) {
+            pending.sent = true;
+        }
+    }
+
+    /// Delivers the result for the command with the given nonce.
+    pub fn resolve(&mut self, nonce: &str, result: Result<json::Value, Error>) {
+        if let Some(pending) = self.pending.remove(nonce) {
+            // The handle might have been dropped, nobody to tell then
+            let _ = pending.sender.send(result);
+        }
+    }
+
+    /// Fails every command, written to the server or not.
+    pub fn fail_all(&mut self, err: &Error) {
+        for (_, pending) in self.pending.drain() {
+            let _ = pending.sender.send(Err(err.clone()));
+        }
+    }
+
+    /// Fails every command that was already written to the server, as their
+    /// responses won't arrive anymore.
+    pub fn fail_sent(&mut self, err: &Error) {
+        self.pending.retain(|_, pending| {
+            if pending.sent {
+                let _ = pending.sender.send(Err(err.clone()));
+            }
+            !pending.sent
+        });
+    }
+}
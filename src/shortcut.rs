@@ -0,0 +1,63 @@
+//! Capturing keyboard shortcuts with the Discord client.
+
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use serde_json as json;
+use crate::{DiscordRpcHandle, Shared, Message, Response, Error, RpcErrorCode};
+
+/// A running shortcut capture. The keys the user presses arrive at the event
+/// handler as `Event::CaptureShortcutChange`, until the capture is stopped or
+/// dropped.
+#[derive(Debug)]
+pub struct ShortcutCapture {
+    shared: Arc<Shared>,
+    stopped: bool,
+}
+
+impl ShortcutCapture {
+    /// Stops the capture. The returned `Response` can be used to wait for the
+    /// server to stop.
+    pub fn stop(mut self) -> Result<Response, Error> {
+        self.stopped = true;
+        self.shared.send(stop_message())
+    }
+}
+
+impl Drop for ShortcutCapture {
+    fn drop(&mut self) {
+        if !self.stopped && self.shared.keep_running.load(Ordering::SeqCst) {
+            let _ = self.shared.send(stop_message());
+        }
+    }
+}
+
+/// Creates the `Message` stopping the capture.
+fn stop_message() -> Message {
+    Message::command("CAPTURE_SHORTCUT", json::json!{{ "action": "STOP" }})
+}
+
+impl DiscordRpcHandle {
+    /// Starts capturing a keyboard shortcut and blocks until the server
+    /// confirms it, or the timeout elapses. The capture runs until the
+    /// returned `ShortcutCapture` is stopped or dropped. Fails with
+    /// `Error::CaptureShortcutAlreadyListening`, if another capture is
+    /// running. On a timeout the capture is stopped, in case the server still
+    /// starts it. In polling mode this must not be called on the polling
+    /// thread.
+    pub fn capture_shortcut(&self, timeout: Duration) -> Result<ShortcutCapture, Error> {
+        let message = Message::command("CAPTURE_SHORTCUT", json::json!{{ "action": "START" }});
+        match self.shared.send(message)?.wait_timeout(timeout) {
+            Ok(_) => Ok(ShortcutCapture{ shared: self.shared.clone(), stopped: false }),
+            // Only a capture we started may be stopped, so there's no guard
+            Err(Error::Rpc(err)) if err.code == RpcErrorCode::CaptureShortcutAlreadyListening =>
+                Err(Error::CaptureShortcutAlreadyListening),
+            Err(Error::Timeout) => {
+                // The START is still on its way, nobody would stop it later
+                let _ = self.shared.send(stop_message());
+                Err(Error::Timeout)
+            },
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Persisting OAuth2 tokens between runs.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+use serde_json as json;
+use crate::{Token, Error};
+
+/// A storage for the OAuth2 `Token`s of applications, so the user doesn't have
+/// to authorize on every launch.
+pub trait TokenStore: Send + Sync {
+    /// Loads the `Token` stored for the application, if any.
+    fn load(&self, app_id: &str) -> Result<Option<Token>, Error>;
+
+    /// Stores the `Token` for the application, replacing the previous one.
+    fn save(&self, app_id: &str, token: &Token) -> Result<(), Error>;
+
+    /// Deletes the `Token` stored for the application.
+    fn delete(&self, app_id: &str) -> Result<(), Error>;
+}
+
+/// A `TokenStore` keeping each token in a JSON file readable only by the
+/// current user. The files are under `$XDG_CONFIG_HOME/dc_rpc_rs` by default.
+#[derive(Debug, Clone)]
+pub struct FileTokenStore {
+    dir: PathBuf,
+}
+
+impl FileTokenStore {
+    /// Creates a `FileTokenStore` in the default directory.
+    pub fn new() -> Result<Self, Error> {
+        let config_dir = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+            .ok_or_else(|| Error::Storage("Could not determine the config directory".into()))?;
+        Ok(Self::with_dir(config_dir.join("dc_rpc_rs")))
+    }
+
+    /// Creates a `FileTokenStore` in the given directory.
+    pub fn with_dir<P: Into<PathBuf>>(dir: P) -> Self {
+        Self{ dir: dir.into() }
+    }
+
+    /// Returns the path of the file the token of the application is stored in.
+    /// Fails, if the application ID would lead out of the directory.
+    fn path(&self, app_id: &str) -> Result<PathBuf, Error> {
+        if app_id.is_empty() || app_id.contains(['/', '\\']) || app_id.contains("..") {
+            return Err(Error::Storage(format!("Invalid application ID: {:?}", app_id)));
+        }
+        Ok(self.dir.join(format!("{}.json", app_id)))
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self, app_id: &str) -> Result<Option<Token>, Error> {
+        let content = match fs::read_to_string(self.path(app_id)?) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::Storage(err.to_string())),
+        };
+        let json: json::Value = json::from_str(&content)
+            .map_err(|err| Error::Storage(format!("Invalid token file: {}", err)))?;
+        let access_token = json["access_token"].as_str()
+            .ok_or_else(|| Error::Storage("No access token in the token file".into()))?;
+        Ok(Some(Token{
+            access_token: access_token.to_string(),
+            refresh_token: json["refresh_token"].as_str().map(str::to_string),
+            expires_at: json["expires_at"].as_u64().map(|t| UNIX_EPOCH + Duration::from_secs(t)),
+            scopes: json["scopes"].as_array()
+                .map(|s| s.iter().filter_map(|s| s.as_str()).map(str::to_string).collect())
+                .unwrap_or_default(),
+        }))
+    }
+
+    fn save(&self, app_id: &str, token: &Token) -> Result<(), Error> {
+        let storage_err = |err: std::io::Error| Error::Storage(err.to_string());
+
+        let mut json = json::json!{{
+            "access_token": token.access_token,
+            "scopes": token.scopes,
+        }};
+        if let Some(refresh_token) = &token.refresh_token {
+            json["refresh_token"] = json::Value::String(refresh_token.clone());
+        }
+        if let Some(expires_at) = token.expires_at {
+            let secs = expires_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            json["expires_at"] = json::Value::Number(secs.into());
+        }
+
+        fs::create_dir_all(&self.dir).map_err(storage_err)?;
+        // Write to a temporary file first, so a crash can't leave a half
+        // written token behind
+        let path = self.path(app_id)?;
+        let tmp_path = path.with_extension("json.tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp_path).map_err(storage_err)?;
+        // The mode only applies to new files
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600)).map_err(storage_err)?;
+        }
+        file.write_all(json.to_string().as_bytes()).map_err(storage_err)?;
+        file.sync_all().map_err(storage_err)?;
+        fs::rename(&tmp_path, &path).map_err(storage_err)
+    }
+
+    fn delete(&self, app_id: &str) -> Result<(), Error> {
+        match fs::remove_file(self.path(app_id)?) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(Error::Storage(err.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a `FileTokenStore` in a fresh temporary directory.
+    fn temp_store(name: &str) -> FileTokenStore {
+        let dir = env::temp_dir().join(format!("dc_rpc_rs_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        FileTokenStore::with_dir(dir)
+    }
+
+    #[test]
+    fn round_trip() {
+        let store = temp_store("round_trip");
+        let token = Token{
+            access_token: "access".into(),
+            refresh_token: Some("refresh".into()),
+            expires_at: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            scopes: vec!["rpc".into(), "identify".into()],
+        };
+        assert_eq!(store.load("123").unwrap(), None);
+        store.save("123", &token).unwrap();
+        assert_eq!(store.load("123").unwrap(), Some(token));
+        store.delete("123").unwrap();
+        assert_eq!(store.load("123").unwrap(), None);
+        // Deleting a missing token is fine
+        store.delete("123").unwrap();
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn invalid_file_is_an_error() {
+        let store = temp_store("invalid_file");
+        fs::create_dir_all(&store.dir).unwrap();
+        fs::write(store.path("123").unwrap(), "not json").unwrap();
+        assert!(matches!(store.load("123"), Err(Error::Storage(_))));
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn path_rejects_escaping_ids() {
+        let store = FileTokenStore::with_dir("tokens");
+        assert!(store.path("123").is_ok());
+        for app_id in ["", "../123", "a/b", "a\\b", ".."] {
+            assert!(matches!(store.path(app_id), Err(Error::Storage(_))), "{:?}", app_id);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Subscribing to the events dispatched by the Discord RPC server.
+
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use serde_json as json;
+use crate::{DiscordRpcHandle, Shared, Message, Response, Error, pid};
+
+/// The events that can be subscribed to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventSubscription {
+    /// The online count of the guild with the given ID changed.
+    GuildStatus{ guild_id: String },
+    /// The user joined a guild.
+    GuildCreate,
+    /// A channel was created in a guild of the user.
+    ChannelCreate,
+    /// The user changed.
+    CurrentUserUpdate,
+    /// The user joined the activity of someone else in Discord.
+    ActivityJoin,
+    /// The user started spectating the activity of someone else in Discord.
+    ActivitySpectate,
+    /// Someone asked to join the activity of the user.
+    ActivityJoinRequest,
+    /// The user was invited to the activity of someone else.
+    ActivityInvite,
+    /// The overlay was opened or closed in the game.
+    Overlay,
+    /// The state of the overlay changed in the game.
+    OverlayUpdate,
+    /// The lobby with the given ID changed.
+    LobbyUpdate{ lobby_id: String },
+    /// The lobby with the given ID was deleted.
+    LobbyDelete{ lobby_id: String },
+    /// A member connected to the lobby with the given ID.
+    LobbyMemberConnect{ lobby_id: String },
+    /// A member of the lobby with the given ID changed.
+    LobbyMemberUpdate{ lobby_id: String },
+    /// A member disconnected from the lobby with the given ID.
+    LobbyMemberDisconnect{ lobby_id: String },
+    /// Data was sent to the lobby with the given ID.
+    LobbyMessage{ lobby_id: String },
+    /// A user joined the voice channel with the given ID.
+    VoiceStateCreate{ channel_id: String },
+    /// The voice state of a user changed in the voice channel with the given
+    /// ID.
+    VoiceStateUpdate{ channel_id: String },
+    /// A user left the voice channel with the given ID.
+    VoiceStateDelete{ channel_id: String },
+    /// A user started speaking in the voice channel with the given ID.
+    SpeakingStart{ channel_id: String },
+    /// A user stopped speaking in the voice channel with the given ID.
+    SpeakingStop{ channel_id: String },
+    /// A message was sent in the text channel with the given ID.
+    MessageCreate{ channel_id: String },
+    /// A message was edited in the text channel with the given ID.
+    MessageUpdate{ channel_id: String },
+    /// A message was deleted in the text channel with the given ID.
+    MessageDelete{ channel_id: String },
+    /// The Discord client showed a notification.
+    NotificationCreate,
+    /// The voice connection of the user changed.
+    VoiceConnectionStatus,
+    /// The voice settings of the user changed.
+    VoiceSettingsUpdate,
+    /// Any other event, with the arguments to subscribe with.
+    Other{ evt: String, args: json::Value },
+}
+
+impl EventSubscription {
+    /// Returns the name of the event.
+    pub fn evt(&self) -> &str {
+        match self {
+            Self::GuildStatus{ .. } => "GUILD_STATUS",
+            Self::GuildCreate => "GUILD_CREATE",
+            Self::ChannelCreate => "CHANNEL_CREATE",
+            Self::CurrentUserUpdate => "CURRENT_USER_UPDATE",
+            Self::ActivityJoin => "ACTIVITY_JOIN",
+            Self::ActivitySpectate => "ACTIVITY_SPECTATE",
+            Self::ActivityJoinRequest => "ACTIVITY_JOIN_REQUEST",
+            Self::ActivityInvite => "ACTIVITY_INVITE",
+            Self::Overlay => "OVERLAY",
+            Self::OverlayUpdate => "OVERLAY_UPDATE",
+            Self::LobbyUpdate{ .. } => "LOBBY_UPDATE",
+            Self::LobbyDelete{ .. } => "LOBBY_DELETE",
+            Self::LobbyMemberConnect{ .. } => "LOBBY_MEMBER_CONNECT",
+            Self::LobbyMemberUpdate{ .. } => "LOBBY_MEMBER_UPDATE",
+            Self::LobbyMemberDisconnect{ .. } => "LOBBY_MEMBER_DISCONNECT",
+            Self::LobbyMessage{ .. } => "LOBBY_MESSAGE",
+            Self::VoiceStateCreate{ .. } => "VOICE_STATE_CREATE",
+            Self::VoiceStateUpdate{ .. } => "VOICE_STATE_UPDATE",
+            Self::VoiceStateDelete{ .. } => "VOICE_STATE_DELETE",
+            Self::SpeakingStart{ .. } => "SPEAKING_START",
+            Self::SpeakingStop{ .. } => "SPEAKING_STOP",
+            Self::MessageCreate{ .. } => "MESSAGE_CREATE",
+            Self::MessageUpdate{ .. } => "MESSAGE_UPDATE",
+            Self::MessageDelete{ .. } => "MESSAGE_DELETE",
+            Self::NotificationCreate => "NOTIFICATION_CREATE",
+            Self::VoiceConnectionStatus => "VOICE_CONNECTION_STATUS",
+            Self::VoiceSettingsUpdate => "VOICE_SETTINGS_UPDATE",
+            Self::Other{ evt, .. } => evt,
+        }
+    }
+
+    /// Returns the arguments to subscribe with.
+    pub fn args(&self) -> json::Value {
+        match self {
+            Self::GuildStatus{ guild_id } => json::json!{{ "guild_id": guild_id }},
+            Self::LobbyUpdate{ lobby_id }
+            | Self::LobbyDelete{ lobby_id }
+            | Self::LobbyMemberConnect{ lobby_id }
+            | Self::LobbyMemberUpdate{ lobby_id }
+            | Self::LobbyMemberDisconnect{ lobby_id }
+            | Self::LobbyMessage{ lobby_id } => json::json!{{ "lobby_id": lobby_id }},
+            Self::VoiceStateCreate{ channel_id }
+            | Self::VoiceStateUpdate{ channel_id }
+            | Self::VoiceStateDelete{ channel_id }
+            | Self::SpeakingStart{ channel_id }
+            | Self::SpeakingStop{ channel_id }
+            | Self::MessageCreate{ channel_id }
+            | Self::MessageUpdate{ channel_id }
+            | Self::MessageDelete{ channel_id } => json::json!{{ "channel_id": channel_id }},
+            Self::Overlay
+            | Self::OverlayUpdate => json::json!{{ "pid": pid() }},
+            Self::GuildCreate
+            | Self::ChannelCreate
+            | Self::CurrentUserUpdate
+            | Self::ActivityJoin
+            | Self::ActivitySpectate
+            | Self::ActivityJoinRequest
+            | Self::ActivityInvite
+            | Self::NotificationCreate
+            | Self::VoiceConnectionStatus
+            | Self::VoiceSettingsUpdate => json::json!{{}},
+            Self::Other{ args, .. } => args.clone(),
+        }
+    }
+
+    /// Creates the `SUBSCRIBE` `Message` for the event.
+    pub(crate) fn subscribe_message(&self) -> Message {
+        Message::subscribe(self.evt(), self.args())
+    }
+}
+
+/// An event subscribed to, along with the nonce of the last `SUBSCRIBE` sent
+/// for it. Forgotten, if the server refuses that `SUBSCRIBE`.
+#[derive(Debug, Clone)]
+pub(crate) struct Subscribed {
+    pub event: EventSubscription,
+    pub nonce: String,
+}
+
+/// A guard of an event subscription. The events arrive at the event handler
+/// while it's alive, it unsubscribes when dropped. The subscription is
+/// renewed automatically after reconnecting.
+#[derive(Debug)]
+pub struct Subscription {
+    shared: Arc<Shared>,
+    event: EventSubscription,
+    response: Response,
+}
+
+impl Subscription {
+    /// Returns the event subscribed to.
+    pub fn event(&self) -> &EventSubscription {
+        &self.event
+    }
+
+    /// Returns the `Response` of the server to the subscription. It receives
+    /// an error, if the server refused it.
+    pub fn response(&self) -> &Response {
+        &self.response
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut subscriptions = self.shared.subscriptions.lock().unwrap();
+        match subscriptions.iter().position(|s| s.event == self.event) {
+            Some(idx) => subscriptions.remove(idx),
+            // Nothing to unsubscribe from, the server refused it
+            None => return,
+        };
+        // Other guards might still want the events
+        let still_needed = subscriptions.iter().any(|s| s.event == self.event);
+        drop(subscriptions);
+        if !still_needed && self.shared.keep_running.load(Ordering::SeqCst) {
+            let _ = self.shared.send(Message::unsubscribe(self.event.evt(), self.event.args()));
+        }
+    }
+}
+
+impl DiscordRpcHandle {
+    /// Subscribes to the given event. Events arrive at the event handler as
+    /// long as the returned `Subscription` is kept alive. Most events need an
+    /// authenticated connection. If the server refuses the subscription, it's
+    /// not renewed after reconnecting.
+    pub fn subscribe(&self, event: EventSubscription) -> Result<Subscription, Error> {
+        let message = event.subscribe_message();
+        let nonce = message.value("nonce").unwrap_or_default().to_string();
+        // Registered before sending, so a quick refusal can't be missed
+        self.shared.subscriptions.lock().unwrap()
+            .push(Subscribed{ event: event.clone(), nonce: nonce.clone() });
+        let response = match self.shared.send(message) {
+            Ok(response) => response,
+            Err(err) => {
+                self.shared.subscriptions.lock().unwrap().retain(|s| s.nonce != nonce);
+                return Err(err);
+            },
+        };
+        Ok(Subscription{ shared: self.shared.clone(), event, response })
+    }
+}
@@ -0,0 +1,146 @@
+//! Implementation of a Unix domain socket `Connection`.
+
+#![cfg(unix)]
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use crate::Connection;
+
+/// Returns the directory the Discord client puts its sockets in.
+fn socket_dir() -> PathBuf {
+    for var in &["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"] {
+        if let Ok(dir) = env::var(var) {
+            return dir.into();
+        }
+    }
+    "/tmp".into()
+}
+
+/// Returns the paths of all 10 socket slots.
+fn socket_paths() -> impl Iterator<Item = PathBuf> {
+    let dir = socket_dir();
+    (0..10).map(move |index| dir.join(format!("discord-ipc-{}", index)))
+}
+
+/// Represents a Unix domain socket `Connection`.
+#[derive(Debug, Default)]
+pub struct UnixSocket {
+    stream: Option<UnixStream>,
+    // What was read, but not asked for yet
+    buffer: Vec<u8>,
+}
+
+impl UnixSocket {
+    /// Creates a new `UnixSocket`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads everything that is available without blocking into the buffer.
+    /// Returns `false`, if the socket was hung up or failed.
+    fn fill_buffer(&mut self) -> bool {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => return false,
+        };
+        if stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let mut chunk = [0u8; 4096];
+        let ok = loop {
+            match stream.read(&mut chunk) {
+                Ok(0) => break false,
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break true,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break false,
+            }
+        };
+        ok && stream.set_nonblocking(false).is_ok()
+    }
+}
+
+impl Connection for UnixSocket {
+    fn open(&mut self) -> bool {
+        if self.is_open() {
+            return true;
+        }
+        // Try all 10 slots
+        for path in socket_paths() {
+            if let Ok(stream) = UnixStream::connect(path) {
+                self.stream = Some(stream);
+                self.buffer.clear();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn is_open(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn close(&mut self) {
+        self.stream = None;
+        self.buffer.clear();
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> bool {
+        if self.buffer.len() < buffer.len() {
+            // The data before a hangup is still served, it might be a Close
+            let alive = self.fill_buffer();
+            if self.buffer.len() < buffer.len() {
+                if !alive {
+                    self.close();
+                }
+                return false;
+            }
+        }
+        buffer.copy_from_slice(&self.buffer[..buffer.len()]);
+        self.buffer.drain(..buffer.len());
+        true
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> bool {
+        let ok = match &mut self.stream {
+            Some(stream) => stream.write_all(buffer).is_ok(),
+            None => return false,
+        };
+        if !ok {
+            self.close();
+        }
+        ok
+    }
+}
+
+/// Represents an asynchronous Unix domain socket `AsyncConnection`.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default)]
+pub struct AsyncUnixSocket;
+
+#[cfg(feature = "tokio")]
+impl AsyncUnixSocket {
+    /// Creates a new `AsyncUnixSocket`.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl crate::AsyncConnection for AsyncUnixSocket {
+    type Stream = tokio::net::UnixStream;
+
+    async fn open(&mut self) -> io::Result<tokio::net::UnixStream> {
+        // Try all 10 slots
+        let mut last_error = None;
+        for path in socket_paths() {
+            match tokio::net::UnixStream::connect(path).await {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.unwrap())
+    }
+}
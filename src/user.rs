@@ -0,0 +1,35 @@
+//! Discord users.
+
+use serde_json as json;
+
+/// Represents a Discord user.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct User {
+    /// The ID of the user.
+    pub id: String,
+    /// The name of the user.
+    pub username: String,
+    /// The discriminator of the user, `0` for users with unique names.
+    pub discriminator: String,
+    /// The display name of the user, if set.
+    pub global_name: Option<String>,
+    /// The avatar hash of the user, if set.
+    pub avatar: Option<String>,
+    /// `true`, if the user is a bot.
+    pub bot: bool,
+}
+
+impl User {
+    /// Creates a `User` from its JSON representation. Returns `None`, if the
+    /// user has no ID.
+    pub(crate) fn from_json(json: &json::Value) -> Option<Self> {
+        Some(Self{
+            id: json["id"].as_str()?.to_string(),
+            username: json["username"].as_str().unwrap_or_default().to_string(),
+            discriminator: json["discriminator"].as_str().unwrap_or_default().to_string(),
+            global_name: json["global_name"].as_str().map(str::to_string),
+            avatar: json["avatar"].as_str().map(str::to_string),
+            bot: json["bot"].as_bool().unwrap_or(false),
+        })
+    }
+}
@@ -0,0 +1,471 @@
+//! Voice states and the voice settings of the user.
+
+use serde_json as json;
+use crate::{DiscordRpcHandle, Message, Response, User, Error};
+
+/// The left and right channel volume of a user, between `0.0` and `1.0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pan {
+    /// The volume of the left channel.
+    pub left: f64,
+    /// The volume of the right channel.
+    pub right: f64,
+}
+
+impl Default for Pan {
+    fn default() -> Self {
+        Self{ left: 1.0, right: 1.0 }
+    }
+}
+
+impl Pan {
+    /// Creates a `Pan` from a balance between `-1.0` (only left) and `1.0`
+    /// (only right), `0.0` being the center.
+    pub fn from_balance(balance: f64) -> Self {
+        Self{
+            left: (1.0 - balance).min(1.0),
+            right: (1.0 + balance).min(1.0),
+        }
+    }
+
+    /// Creates a `Pan` from its JSON representation.
+    fn from_json(json: &json::Value) -> Self {
+        Self{
+            left: json["left"].as_f64().unwrap_or(1.0),
+            right: json["right"].as_f64().unwrap_or(1.0),
+        }
+    }
+}
+
+/// The way the local user hears another user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserVoiceSettings {
+    /// The ID of the user.
+    pub user_id: String,
+    /// The volume of the user, between `0` and `200`.
+    pub volume: f64,
+    /// `true`, if the user is muted locally.
+    pub mute: bool,
+    /// The panning of the user.
+    pub pan: Pan,
+}
+
+impl UserVoiceSettings {
+    /// Creates the `UserVoiceSettings` from its JSON representation.
+    fn from_json(json: &json::Value) -> Self {
+        Self{
+            user_id: json["user_id"].as_str().unwrap_or_default().to_string(),
+            volume: json["volume"].as_f64().unwrap_or(100.0),
+            mute: json["mute"].as_bool().unwrap_or(false),
+            pan: Pan::from_json(&json["pan"]),
+        }
+    }
+}
+
+/// The voice state of a user in a voice channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceState {
+    /// The user the state belongs to.
+    pub user: User,
+    /// The nickname of the user in the guild, if set.
+    pub nick: Option<String>,
+    /// The volume the local user hears this user with, between `0` and `200`.
+    pub volume: f64,
+    /// `true`, if the local user muted this user.
+    pub mute: bool,
+    /// The panning the local user hears this user with.
+    pub pan: Pan,
+    /// `true`, if the user is muted by the server.
+    pub server_mute: bool,
+    /// `true`, if the user is deafened by the server.
+    pub server_deaf: bool,
+    /// `true`, if the user muted themselves.
+    pub self_mute: bool,
+    /// `true`, if the user deafened themselves.
+    pub self_deaf: bool,
+    /// `true`, if the user is suppressed.
+    pub suppress: bool,
+}
+
+impl VoiceState {
+    /// Creates a `VoiceState` from its JSON representation. Returns `None`, if
+    /// there's no user in it.
+    pub(crate) fn from_json(json: &json::Value) -> Option<Self> {
+        let state = &json["voice_state"];
+        Some(Self{
+            user: User::from_json(&json["user"])?,
+            nick: json["nick"].as_str().map(str::to_string),
+            volume: json["volume"].as_f64().unwrap_or(100.0),
+            mute: json["mute"].as_bool().unwrap_or(false),
+            pan: Pan::from_json(&json["pan"]),
+            server_mute: state["mute"].as_bool().unwrap_or(false),
+            server_deaf: state["deaf"].as_bool().unwrap_or(false),
+            self_mute: state["self_mute"].as_bool().unwrap_or(false),
+            self_deaf: state["self_deaf"].as_bool().unwrap_or(false),
+            suppress: state["suppress"].as_bool().unwrap_or(false),
+        })
+    }
+}
+
+/// An audio device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioDevice {
+    /// The ID of the device.
+    pub id: String,
+    /// The name of the device.
+    pub name: String,
+}
+
+/// The settings of the audio input or output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceIo {
+    /// The ID of the device in use.
+    pub device_id: String,
+    /// The volume, between `0` and `100` for the input and between `0` and
+    /// `200` for the output.
+    pub volume: f64,
+    /// The devices that can be selected.
+    pub available_devices: Vec<AudioDevice>,
+}
+
+impl VoiceIo {
+    /// Creates a `VoiceIo` from its JSON representation.
+    fn from_json(json: &json::Value) -> Self {
+        Self{
+            device_id: json["device_id"].as_str().unwrap_or_default().to_string(),
+            volume: json["volume"].as_f64().unwrap_or_default(),
+            available_devices: json["available_devices"].as_array()
+                .map(|d| d.iter().filter_map(|d| Some(AudioDevice{
+                    id: d["id"].as_str()?.to_string(),
+                    name: d["name"].as_str().unwrap_or_default().to_string(),
+                })).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// How the voice input is activated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VoiceModeType {
+    /// The input is sent while the shortcut is held.
+    PushToTalk,
+    /// The input is sent when it's above the threshold.
+    VoiceActivity,
+}
+
+impl VoiceModeType {
+    /// Returns the name of the mode in the RPC protocol.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::PushToTalk => "PUSH_TO_TALK",
+            Self::VoiceActivity => "VOICE_ACTIVITY",
+        }
+    }
+}
+
+/// A key of a keyboard shortcut.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShortcutKey {
+    /// The type of the key: `0` for keyboard keys, `1` for mouse buttons,
+    /// `2` for keyboard modifiers and `3` for gamepad buttons.
+    pub ty: i32,
+    /// The code of the key.
+    pub code: i32,
+    /// The name of the key.
+    pub name: String,
+}
+
+impl ShortcutKey {
+    /// Creates a `ShortcutKey` from its JSON representation.
+    pub(crate) fn from_json(json: &json::Value) -> Self {
+        Self{
+            ty: json["type"].as_i64().unwrap_or_default() as i32,
+            code: json["code"].as_i64().unwrap_or_default() as i32,
+            name: json["name"].as_str().unwrap_or_default().to_string(),
+        }
+    }
+
+    /// Returns the JSON representation of the `ShortcutKey`.
+    pub(crate) fn to_json(&self) -> json::Value {
+        json::json!{{ "type": self.ty, "code": self.code, "name": self.name }}
+    }
+}
+
+/// The voice activation settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceMode {
+    /// How the voice input is activated.
+    pub ty: VoiceModeType,
+    /// `true`, if the voice activity threshold is set automatically.
+    pub auto_threshold: bool,
+    /// The voice activity threshold in dB, between `-100` and `0`.
+    pub threshold: f64,
+    /// The push to talk shortcut.
+    pub shortcut: Vec<ShortcutKey>,
+    /// The push to talk release delay in milliseconds, between `0` and `2000`.
+    pub delay: f64,
+}
+
+/// The voice settings of the user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceSettings {
+    /// The input settings.
+    pub input: VoiceIo,
+    /// The output settings.
+    pub output: VoiceIo,
+    /// The voice activation settings.
+    pub mode: VoiceMode,
+    /// `true`, if automatic gain control is on.
+    pub automatic_gain_control: bool,
+    /// `true`, if echo cancellation is on.
+    pub echo_cancellation: bool,
+    /// `true`, if noise suppression is on.
+    pub noise_suppression: bool,
+    /// `true`, if voice quality of service is on.
+    pub qos: bool,
+    /// `true`, if the silence warning notice is shown.
+    pub silence_warning: bool,
+    /// `true`, if the user is deafened.
+    pub deaf: bool,
+    /// `true`, if the user is muted.
+    pub mute: bool,
+}
+
+impl VoiceSettings {
+    /// Creates the `VoiceSettings` from its JSON representation.
+    pub(crate) fn from_json(json: &json::Value) -> Self {
+        let mode = &json["mode"];
+        let flag = |key: &str| json[key].as_bool().unwrap_or(false);
+        Self{
+            input: VoiceIo::from_json(&json["input"]),
+            output: VoiceIo::from_json(&json["output"]),
+            mode: VoiceMode{
+                ty: if mode["type"].as_str() == Some("PUSH_TO_TALK") {
+                    VoiceModeType::PushToTalk
+                }
+                else {
+                    VoiceModeType::VoiceActivity
+                },
+                auto_threshold: mode["auto_threshold"].as_bool().unwrap_or(false),
+                threshold: mode["threshold"].as_f64().unwrap_or_default(),
+                shortcut: mode["shortcut"].as_array()
+                    .map(|s| s.iter().map(ShortcutKey::from_json).collect())
+                    .unwrap_or_default(),
+                delay: mode["delay"].as_f64().unwrap_or_default(),
+            },
+            automatic_gain_control: flag("automatic_gain_control"),
+            echo_cancellation: flag("echo_cancellation"),
+            noise_suppression: flag("noise_suppression"),
+            qos: flag("qos"),
+            silence_warning: flag("silence_warning"),
+            deaf: flag("deaf"),
+            mute: flag("mute"),
+        }
+    }
+}
+
+/// A change to the `VoiceSettings`. Only the fields that are set are sent,
+/// the rest is left as it is.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VoiceSettingsPatch {
+    /// The ID of the input device to use.
+    pub input_device_id: Option<String>,
+    /// The input volume, between `0` and `100`.
+    pub input_volume: Option<f64>,
+    /// The ID of the output device to use.
+    pub output_device_id: Option<String>,
+    /// The output volume, between `0` and `200`.
+    pub output_volume: Option<f64>,
+    /// How the voice input is activated.
+    pub mode: Option<VoiceModeType>,
+    /// `true`, if the voice activity threshold is set automatically.
+    pub auto_threshold: Option<bool>,
+    /// The voice activity threshold in dB, between `-100` and `0`.
+    pub threshold: Option<f64>,
+    /// The push to talk shortcut.
+    pub shortcut: Option<Vec<ShortcutKey>>,
+    /// The push to talk release delay in milliseconds, between `0` and `2000`.
+    pub delay: Option<f64>,
+    /// Turns automatic gain control on or off.
+    pub automatic_gain_control: Option<bool>,
+    /// Turns echo cancellation on or off.
+    pub echo_cancellation: Option<bool>,
+    /// Turns noise suppression on or off.
+    pub noise_suppression: Option<bool>,
+    /// Turns voice quality of service on or off.
+    pub qos: Option<bool>,
+    /// Shows or hides the silence warning notice.
+    pub silence_warning: Option<bool>,
+    /// Deafens or undeafens the user.
+    pub deaf: Option<bool>,
+    /// Mutes or unmutes the user.
+    pub mute: Option<bool>,
+}
+
+impl VoiceSettingsPatch {
+    /// Returns the JSON representation of the patch, with only the fields that
+    /// are set.
+    fn to_json(&self) -> json::Value {
+        fn io(device_id: &Option<String>, volume: Option<f64>) -> json::Map<String, json::Value> {
+            let mut io = json::Map::new();
+            if let Some(device_id) = device_id {
+                io.insert("device_id".into(), device_id.clone().into());
+            }
+            if let Some(volume) = volume {
+                io.insert("volume".into(), volume.into());
+            }
+            io
+        }
+
+        let mut args = json::Map::new();
+        let input = io(&self.input_device_id, self.input_volume);
+        if !input.is_empty() {
+            args.insert("input".into(), input.into());
+        }
+        let output = io(&self.output_device_id, self.output_volume);
+        if !output.is_empty() {
+            args.insert("output".into(), output.into());
+        }
+
+        let mut mode = json::Map::new();
+        if let Some(ty) = self.mode {
+            mode.insert("type".into(), ty.as_str().into());
+        }
+        if let Some(auto_threshold) = self.auto_threshold {
+            mode.insert("auto_threshold".into(), auto_threshold.into());
+        }
+        if let Some(threshold) = self.threshold {
+            mode.insert("threshold".into(), threshold.into());
+        }
+        if let Some(shortcut) = &self.shortcut {
+            mode.insert("shortcut".into(), shortcut.iter().map(ShortcutKey::to_json).collect());
+        }
+        if let Some(delay) = self.delay {
+            mode.insert("delay".into(), delay.into());
+        }
+        if !mode.is_empty() {
+            args.insert("mode".into(), mode.into());
+        }
+
+        let flags = [
+            ("automatic_gain_control", self.automatic_gain_control),
+            ("echo_cancellation", self.echo_cancellation),
+            ("noise_suppression", self.noise_suppression),
+            ("qos", self.qos),
+            ("silence_warning", self.silence_warning),
+            ("deaf", self.deaf),
+            ("mute", self.mute),
+        ];
+        for (key, value) in flags {
+            if let Some(value) = value {
+                args.insert(key.into(), value.into());
+            }
+        }
+        args.into()
+    }
+}
+
+/// Creates the arguments of a `SET_USER_VOICE_SETTINGS` command, checking
+/// the volume and pan ranges.
+fn user_voice_settings_args(
+    user_id: &str,
+    volume: Option<f64>,
+    mute: Option<bool>,
+    pan: Option<f64>,
+) -> Result<json::Value, Error> {
+    let mut args = json::json!{{ "user_id": user_id }};
+    if let Some(volume) = volume {
+        if !(0.0..=200.0).contains(&volume) {
+            return Err(Error::InvalidArgument(format!("Volume {} is not between 0 and 200", volume)));
+        }
+        args["volume"] = volume.into();
+    }
+    if let Some(mute) = mute {
+        args["mute"] = mute.into();
+    }
+    if let Some(pan) = pan {
+        if !(-1.0..=1.0).contains(&pan) {
+            return Err(Error::InvalidArgument(format!("Pan {} is not between -1 and 1", pan)));
+        }
+        let pan = Pan::from_balance(pan);
+        args["pan"] = json::json!{{ "left": pan.left, "right": pan.right }};
+    }
+    Ok(args)
+}
+
+impl DiscordRpcHandle {
+    /// Gets the voice settings of the user. Needs an authenticated connection.
+    pub fn get_voice_settings(&self) -> Result<Response<VoiceSettings>, Error> {
+        let response = self.shared.send(Message::command("GET_VOICE_SETTINGS", json::json!{{}}))?;
+        Ok(response.map(|data| Ok(VoiceSettings::from_json(&data?))))
+    }
+
+    /// Changes the voice settings of the user. The `Response` receives the
+    /// settings after the change.
+    pub fn set_voice_settings(&self, patch: &VoiceSettingsPatch) -> Result<Response<VoiceSettings>, Error> {
+        let response = self.shared.send(Message::command("SET_VOICE_SETTINGS", patch.to_json()))?;
+        Ok(response.map(|data| Ok(VoiceSettings::from_json(&data?))))
+    }
+
+    /// Changes how the local user hears the user with the given ID. The
+    /// volume is between `0` and `200`, the pan is a balance between `-1.0`
+    /// (only left) and `1.0` (only right). Only the given settings are
+    /// changed. The `Response` receives the settings after the change.
+    pub fn set_user_voice_settings(
+        &self,
+        user_id: &str,
+        volume: Option<f64>,
+        mute: Option<bool>,
+        pan: Option<f64>,
+    ) -> Result<Response<UserVoiceSettings>, Error> {
+        let args = user_voice_settings_args(user_id, volume, mute, pan)?;
+        let response = self.shared.send(Message::command("SET_USER_VOICE_SETTINGS", args))?;
+        Ok(response.map(|data| Ok(UserVoiceSettings::from_json(&data?))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_patch_sends_nothing() {
+        assert_eq!(VoiceSettingsPatch::default().to_json(), json::json!{{}});
+    }
+
+    #[test]
+    fn patch_sends_set_fields() {
+        let patch = VoiceSettingsPatch{
+            output_volume: Some(150.0),
+            mode: Some(VoiceModeType::PushToTalk),
+            delay: Some(20.0),
+            mute: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(patch.to_json(), json::json!{{
+            "output": { "volume": 150.0 },
+            "mode": { "type": "PUSH_TO_TALK", "delay": 20.0 },
+            "mute": true,
+        }});
+    }
+
+    #[test]
+    fn pan_from_balance() {
+        assert_eq!(Pan::from_balance(0.0), Pan{ left: 1.0, right: 1.0 });
+        assert_eq!(Pan::from_balance(-1.0), Pan{ left: 1.0, right: 0.0 });
+        assert_eq!(Pan::from_balance(0.5), Pan{ left: 0.5, right: 1.0 });
+    }
+
+    #[test]
+    fn user_settings_validate_ranges() {
+        let args = user_voice_settings_args("1", Some(50.0), None, Some(-0.5)).unwrap();
+        assert_eq!(args, json::json!{{
+            "user_id": "1",
+            "volume": 50.0,
+            "pan": { "left": 1.0, "right": 0.5 },
+        }});
+        assert!(matches!(user_voice_settings_args("1", None, None, Some(1.5)), Err(Error::InvalidArgument(_))));
+        assert!(matches!(user_voice_settings_args("1", None, None, Some(-1.5)), Err(Error::InvalidArgument(_))));
+        assert!(matches!(user_voice_settings_args("1", Some(201.0), None, None), Err(Error::InvalidArgument(_))));
+    }
+}
@@ -3,6 +3,7 @@
 #![cfg(target_os = "windows")]
 
 /// WINAPI bindings.
+#[allow(clippy::upper_case_acronyms)]
 mod winapi {
     // Type aliases
 
@@ -79,11 +80,6 @@ mod winapi {
             hFile: HANDLE,
         ) -> BOOL;
 
-        pub fn WaitNamedPipeW(
-            lpNamedPipeName: LPCWSTR,
-            nTimeOut       : DWORD  ,
-        ) -> BOOL;
-
         pub fn PeekNamedPipe(
             hNamedPipe            : HANDLE ,
             lpBuffer              : LPVOID ,
@@ -133,9 +129,9 @@ impl Connection for NamedPipe {
         if self.is_open() {
             return true;
         }
-        // Try all 10 slots
-        let mut index = 0;
-        loop {
+        // Try all 10 slots. A busy pipe is not waited for, opening must not
+        // block, it's retried on the next open instead.
+        for index in 0..10 {
             let pipe_name = format!(r#"\\.\pipe\discord-ipc-{}"#, index);
             let pipe_name = utf8_to_utf16(&pipe_name);
             let pipe_name = pipe_name.as_ptr();
@@ -147,21 +143,12 @@ impl Connection for NamedPipe {
             }
 
             let last_error = unsafe{ GetLastError() };
-            if last_error == ERROR_FILE_NOT_FOUND {
+            if last_error != ERROR_FILE_NOT_FOUND && last_error != ERROR_PIPE_BUSY {
                 // Can't do anything
-                if index < 9 {
-                    index += 1;
-                    continue;
-                }
+                return false;
             }
-            else if last_error == ERROR_PIPE_BUSY {
-                if unsafe{ WaitNamedPipeW(pipe_name, 10000) } == 0 {
-                    return false;
-                }
-                continue;
-            }
-            return false;
         }
+        false
     }
 
     fn is_open(&self) -> bool {
@@ -199,7 +186,7 @@ impl Connection for NamedPipe {
     }
 
     fn write(&mut self, buffer: &[u8]) -> bool {
-        if buffer.len() == 0 {
+        if buffer.is_empty() {
             return true;
         }
         if !self.is_open() {